@@ -0,0 +1,243 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rocket::tokio::sync::RwLock;
+use rocket::tokio::task::spawn_blocking;
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+use crate::fellestrekk::{valid_name, PlayerStats, Standing};
+use crate::games::RoundResult;
+use crate::storage::Storage;
+
+/// The cookie an account's token is kept in
+pub const COOKIE: &str = "account";
+/// What a new account's wallet holds
+pub const STARTING_WALLET: i64 = 1000;
+pub const MIN_PASSWORD_LEN: usize = 8;
+/// What accounts are saved as in the [`Storage`], keyed by token
+const ACCOUNTS: &str = "accounts";
+
+/// Someone who comes back, so their wallet, statistics and nickname carry over between sessions
+///
+/// Every account starts out anonymous, known only by the token in the browser's cookie.
+/// Registering gives it a username and password, so it can be logged into from anywhere.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    token: String,
+    username: Option<String>,
+    /// An argon2 hash in PHC format
+    password_hash: Option<String>,
+    nickname: Option<String>,
+    wallet: i64,
+    #[serde(default)]
+    stats: PlayerStats,
+}
+
+/// What the owner of an account gets to see of it
+#[derive(Debug, Serialize)]
+pub struct AccountInfo {
+    pub username: Option<String>,
+    pub nickname: Option<String>,
+    pub wallet: i64,
+    pub stats: PlayerStats,
+}
+
+impl Account {
+    fn new() -> Self {
+        Account {
+            token: format!("{:032x}", rand::random::<u128>()),
+            username: None,
+            password_hash: None,
+            nickname: None,
+            wallet: STARTING_WALLET,
+            stats: PlayerStats::default(),
+        }
+    }
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+    /// What the player goes by when they don't give a name
+    pub fn display_name(&self) -> Option<&str> {
+        self.nickname.as_deref().or(self.username.as_deref())
+    }
+    pub fn info(&self) -> AccountInfo {
+        AccountInfo {
+            username: self.username.clone(),
+            nickname: self.nickname.clone(),
+            wallet: self.wallet,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccountError {
+    InvalidName,
+    NameTaken,
+    PasswordTooShort,
+    WrongLogin,
+    AlreadyRegistered,
+    NoAccount,
+}
+
+impl Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountError::InvalidName => write!(f, "Invalid name"),
+            AccountError::NameTaken => write!(f, "Name taken"),
+            AccountError::PasswordTooShort => write!(f, "Password must be at least {MIN_PASSWORD_LEN} characters"),
+            AccountError::WrongLogin => write!(f, "Wrong username or password"),
+            AccountError::AlreadyRegistered => write!(f, "Already registered"),
+            AccountError::NoAccount => write!(f, "No account"),
+        }
+    }
+}
+
+/// Every account by token, shared by the routes and all sessions
+#[derive(Debug, Clone)]
+pub struct Accounts {
+    accounts: Arc<RwLock<HashMap<String, Account>>>,
+    storage: Option<Storage>,
+}
+
+impl Accounts {
+    pub fn new(storage: Option<Storage>) -> Self {
+        Accounts { accounts: Arc::default(), storage }
+    }
+    /// Reads back every account that was saved
+    pub async fn restore(&self) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        match storage.load_all::<Account>(ACCOUNTS).await {
+            Ok(accounts) => {
+                *self.accounts.write().await = accounts.into_iter().map(|(_, a)| (a.token.clone(), a)).collect();
+            }
+            Err(e) => eprintln!("Couldn't restore accounts: {e}"),
+        }
+    }
+    async fn save(&self, account: &Account) {
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.save(ACCOUNTS, &account.token, account).await {
+                eprintln!("Couldn't save account: {e}");
+            }
+        }
+    }
+    pub async fn get(&self, token: &str) -> Option<Account> {
+        self.accounts.read().await.get(token).cloned()
+    }
+    pub async fn wallet(&self, token: &str) -> Option<i64> {
+        self.accounts.read().await.get(token).map(|a| a.wallet)
+    }
+    pub async fn create(&self) -> Account {
+        let account = Account::new();
+        self.accounts.write().await.insert(account.token.clone(), account.clone());
+        self.save(&account).await;
+        account
+    }
+    /// Gives an account a username and password, a new one if there's no anonymous account to keep
+    pub async fn register(&self, token: Option<&str>, username: &str, password: &str) -> Result<Account, AccountError> {
+        if !valid_name(username) {
+            return Err(AccountError::InvalidName);
+        }
+        if password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(AccountError::PasswordTooShort);
+        }
+        let hash = hash_password(password.to_owned()).await;
+
+        let mut accounts = self.accounts.write().await;
+        if accounts.values().any(|a| a.username.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(username))) {
+            return Err(AccountError::NameTaken);
+        }
+        let account = match token.and_then(|t| accounts.get_mut(t)) {
+            Some(account) if account.username.is_some() => return Err(AccountError::AlreadyRegistered),
+            Some(account) => account,
+            None => {
+                let account = Account::new();
+                accounts.entry(account.token.clone()).or_insert(account)
+            }
+        };
+        account.username = Some(username.to_owned());
+        account.password_hash = Some(hash);
+        let account = account.clone();
+        drop(accounts);
+        self.save(&account).await;
+        Ok(account)
+    }
+    pub async fn login(&self, username: &str, password: &str) -> Result<Account, AccountError> {
+        let account = self.accounts.read().await.values()
+            .find(|a| a.username.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(username)))
+            .cloned()
+            .ok_or(AccountError::WrongLogin)?;
+        let hash = account.password_hash.clone().ok_or(AccountError::WrongLogin)?;
+        if verify_password(password.to_owned(), hash).await {
+            Ok(account)
+        } else {
+            Err(AccountError::WrongLogin)
+        }
+    }
+    pub async fn set_nickname(&self, token: &str, nickname: &str) -> Result<Account, AccountError> {
+        if !valid_name(nickname) {
+            return Err(AccountError::InvalidName);
+        }
+        let account = {
+            let mut accounts = self.accounts.write().await;
+            let account = accounts.get_mut(token).ok_or(AccountError::NoAccount)?;
+            account.nickname = Some(nickname.to_owned());
+            account.clone()
+        };
+        self.save(&account).await;
+        Ok(account)
+    }
+    /// Counts a round's result in the account's statistics and pays out its wallet
+    ///
+    /// An account that never picked a nickname goes by the name it played under.
+    pub async fn record(&self, token: &str, name: &str, result: &RoundResult) {
+        let account = {
+            let mut accounts = self.accounts.write().await;
+            let Some(account) = accounts.get_mut(token) else {
+                return;
+            };
+            account.stats.add(result);
+            account.wallet += result.net;
+            if account.display_name().is_none() {
+                account.nickname = Some(name.to_owned());
+            }
+            account.clone()
+        };
+        self.save(&account).await;
+    }
+    /// The accounts that have played, for the leaderboard
+    pub async fn standings(&self) -> Vec<Standing> {
+        self.accounts.read().await.values()
+            .filter(|a| a.stats.tally.hands > 0)
+            .filter_map(|a| Some(Standing { name: a.display_name()?.to_owned(), stats: a.stats.clone() }))
+            .collect()
+    }
+    /// The statistics of the account with this username or nickname
+    pub async fn stats(&self, name: &str) -> Option<PlayerStats> {
+        let accounts = self.accounts.read().await;
+        accounts.values().find(|a| a.username.as_deref() == Some(name))
+            .or_else(|| accounts.values().find(|a| a.nickname.as_deref() == Some(name)))
+            .map(|a| a.stats.clone())
+    }
+}
+
+/// Hashing is slow on purpose, so it's kept off the async workers
+pub(crate) async fn hash_password(password: String) -> String {
+    spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default().hash_password(password.as_bytes(), &salt)
+            .expect("hashing with default parameters")
+            .to_string()
+    }).await.expect("hashing task panicked")
+}
+
+pub(crate) async fn verify_password(password: String, hash: String) -> bool {
+    spawn_blocking(move || {
+        PasswordHash::new(&hash)
+            .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+    }).await.unwrap_or(false)
+}
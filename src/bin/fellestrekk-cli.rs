@@ -0,0 +1,299 @@
+//! Plays Fellestrekk from a terminal, mostly for debugging the server
+//!
+//! Connects to the `/ws` endpoint, hosts a game or joins one by its code and prints what happens at the table.
+//! Actions are typed in a line at a time, see [`HELP`].
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufRead};
+use std::net::TcpStream;
+use std::process::exit;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use fellestrekk::card::Card;
+use fellestrekk::hand::Hand;
+use websocket::sync::Writer;
+use websocket::{ClientBuilder, OwnedMessage};
+
+const DEFAULT_SERVER: &str = "ws://127.0.0.1:8000/ws";
+
+const USAGE: &str = "\
+Usage: fellestrekk-cli [--server URL] <COMMAND>
+
+Commands:
+    host [GAME] [NAME]              hosts a new game, BLACKJACK unless another is given
+    join CODE [NAME] [PASSWORD]     joins the game with that code
+    spectate CODE [NAME] [PASSWORD] watches the game with that code
+
+The server defaults to ws://127.0.0.1:8000/ws.";
+
+const HELP: &str = "\
+h hit    s stand    d double down    u surrender    p split    a hint
+n bet 100 and start a round    b N bet N    start    count N    side PAIRS|21+3 N
+/COMMAND sends a raw protocol command, like /SET dealer S17
+q quits, anything else is said in the chat";
+
+type Socket = Arc<Mutex<Writer<TcpStream>>>;
+
+fn send(socket: &Socket, text: String) {
+    let sent = socket.lock().unwrap().send_message(&OwnedMessage::Text(text));
+    if let Err(e) = sent {
+        eprintln!("Couldn't send: {e}");
+        exit(1);
+    }
+}
+
+/// What the client knows of the table, pieced together from the server's messages
+#[derive(Default)]
+struct Table {
+    /// Our player id, once the roster tells us
+    me: Option<String>,
+    my_name: Option<String>,
+    names: HashMap<String, String>,
+    hand: Hand,
+    dealer: Hand,
+    /// Money won or lost since connecting
+    balance: i64,
+}
+
+impl Table {
+    fn name<'a>(&'a self, pid: &'a str) -> &'a str {
+        self.names.get(pid).map_or(pid, |n| n)
+    }
+    fn show_hands(&self) {
+        print!("Dealer:");
+        self.dealer.print();
+        print!("You:   ");
+        self.hand.print();
+    }
+    fn receive(&mut self, msg: &str) {
+        let (word, rest) = msg.split_once(' ').unwrap_or((msg, ""));
+        match word {
+            "HOST_OK" => {
+                self.me = Some("0".to_owned());
+                println!("Hosting game {rest}");
+            }
+            "JOIN_OK" => {
+                let (code, name) = rest.split_once(' ').unwrap_or((rest, ""));
+                self.my_name = Some(name.to_owned());
+                println!("Joined game {code} as {name}");
+            }
+            "SPECTATE_OK" => println!("Watching game {rest}"),
+            "PLAYERS" | "WATCHERS" => {
+                let words: Vec<&str> = rest.split(' ').filter(|w| !w.is_empty()).collect();
+                for pair in words.chunks(2) {
+                    if let &[pid, name] = pair {
+                        if self.my_name.as_deref() == Some(name) {
+                            self.me = Some(pid.to_owned());
+                        }
+                        self.names.insert(pid.to_owned(), name.to_owned());
+                    }
+                }
+                if word == "PLAYERS" {
+                    let names: Vec<&str> = words.chunks(2).filter_map(|p| p.get(1).copied()).collect();
+                    println!("Players: {}", names.join(", "));
+                }
+            }
+            "CHAT_MSG" => {
+                let (pid, text) = rest.split_once(' ').unwrap_or((rest, ""));
+                println!("{}: {text}", self.name(pid));
+            }
+            "SYSTEM" => println!("* {rest}"),
+            "ERROR" => println!("! {rest}"),
+            "HOST_IS" => println!("{} is the host", self.name(rest)),
+            "TIMER" => if let Some((pid, seconds)) = rest.split_once(' ') {
+                if Some(pid) == self.me.as_deref() {
+                    println!("Your turn, {seconds}s");
+                }
+            },
+            "BLACKJACK" => self.blackjack(rest),
+            "SETTING" | "ROUND_OVER" | "SCORES" | "FINISHED" | "LOCK" | "UNLOCK" | "PUBLIC" | "PRIVATE" | "TURNTIME" => {
+                println!("{msg}");
+            }
+            _ => println!("? {msg}"),
+        }
+    }
+    fn blackjack(&mut self, event: &str) {
+        let (word, rest) = event.split_once(' ').unwrap_or((event, ""));
+        let args: Vec<&str> = rest.split(' ').collect();
+        let card = |i: usize| args.get(i).and_then(|c| c.parse::<Card>().ok());
+        match word {
+            "START" => {
+                self.hand = Hand::default();
+                self.dealer = Hand::default();
+                println!("--- New round ---");
+            }
+            "SEAT" => if let &[pid, name] = &*args {
+                if Some(pid) != self.me.as_deref() {
+                    println!("{name} is dealt in");
+                }
+            },
+            "DOWNCARD" => if let Some(card) = card(0) {
+                self.hand.add_card(card);
+            },
+            "PLAYERDRAW" => if let Some(card) = card(1) {
+                if Some(args[0]) == self.me.as_deref() {
+                    self.hand.add_card(card);
+                } else {
+                    println!("{} drew {card}", self.name(args[0]));
+                }
+            },
+            "DEALERDRAW" => if let Some(card) = card(0) {
+                self.dealer.add_card(card);
+            },
+            // The dealer's down card goes in front of what they've shown
+            "REVEALDOWNS" => if let Some(down) = card(0) {
+                let mut dealer = Hand::new([down]);
+                for &card in self.dealer.cards() {
+                    dealer.add_card(card);
+                }
+                self.dealer = dealer;
+            },
+            "STATUS" => {
+                self.show_hands();
+                let options: Vec<&str> = args.iter().filter_map(|&flag| match flag {
+                    "H" => Some("[h]it"),
+                    "S" => Some("[s]tand"),
+                    "D" => Some("[d]ouble"),
+                    "U" => Some("s[u]rrender"),
+                    "P" => Some("s[p]lit"),
+                    "N" => Some("[n]ew round"),
+                    _ => None,
+                }).collect();
+                println!("{}", options.join(" "));
+            }
+            "WIN" | "LOSE" | "DRAW" => {
+                self.show_hands();
+                let verdict = match word {
+                    "WIN" => "You won!",
+                    "LOSE" => "You lost.",
+                    _ => "You tied, you get the bet back.",
+                };
+                println!("{verdict}");
+            }
+            "TAKEMONEY" | "SENDMONEY" => {
+                let amount: i64 = rest.parse().unwrap_or(0);
+                self.balance += if word == "TAKEMONEY" { -amount } else { amount };
+                println!("Balance: {:+}", self.balance);
+            }
+            "HINT" => println!("Basic strategy says {rest}"),
+            "QUIZ" => println!("Quiz: what's the {} count? Answer with count N", rest.to_lowercase()),
+            "COUNTED" => if let &[verdict, answer, correct, asked] = &*args {
+                println!("{verdict}, the count was {answer} ({correct}/{asked} right)");
+            },
+            "SIDEBET" => println!("Side bet placed: {rest}"),
+            "SIDEWIN" => println!("Side bet won: {rest}"),
+            "SIDELOSE" => println!("Side bet lost: {rest}"),
+            "DECKSIZE" | "VALUEUPDATE" => (),
+            _ => println!("? BLACKJACK {event}"),
+        }
+    }
+}
+
+/// Turns a line typed in into what's sent to the server
+fn action(line: &str) -> Vec<String> {
+    let game = |action: &str| vec![format!("BLACKJACK {action}")];
+    let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+    match word {
+        "h" => game("HIT"),
+        "s" => game("STAND"),
+        "d" => game("DOUBLEDOWN"),
+        "u" => game("SURRENDER"),
+        "p" => game("SPLIT"),
+        "a" => game("HINT"),
+        "n" => vec!["BLACKJACK BET 100".to_owned(), "START".to_owned()],
+        "b" => game(&format!("BET {rest}")),
+        "count" => game(&format!("COUNT {rest}")),
+        "side" => game(&format!("SIDEBET {}", rest.to_uppercase())),
+        "start" => vec!["START".to_owned()],
+        _ if line.starts_with('/') => vec![line[1..].to_owned()],
+        _ => vec![format!("CHAT {line}")],
+    }
+}
+
+/// The first command sent, from the command line
+fn first_command(args: &[String]) -> Option<String> {
+    let (command, rest) = args.split_first()?;
+    match &**command {
+        "host" => {
+            let game = rest.first().map_or("BLACKJACK".to_owned(), |g| g.to_uppercase());
+            Some(match rest.get(1) {
+                Some(name) => format!("HOST {game} {name}"),
+                None => format!("HOST {game}"),
+            })
+        }
+        "join" | "spectate" if !rest.is_empty() => Some(format!("{} {}", command.to_uppercase(), rest.join(" "))),
+        _ => None,
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut server = DEFAULT_SERVER.to_owned();
+    if args.first().map(|s| &**s) == Some("--server") && args.len() > 1 {
+        server = args.remove(1);
+        args.remove(0);
+    }
+    let Some(first) = first_command(&args) else {
+        eprintln!("{USAGE}");
+        exit(2);
+    };
+
+    let client = ClientBuilder::new(&server)
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid server address: {e}");
+            exit(2);
+        })
+        .connect_insecure()
+        .unwrap_or_else(|e| {
+            eprintln!("Couldn't connect to {server}: {e}");
+            exit(1);
+        });
+    let (mut reader, writer) = client.split().expect("splitting a TCP stream");
+    let socket: Socket = Arc::new(Mutex::new(writer));
+    send(&socket, first);
+
+    let pong = socket.clone();
+    thread::spawn(move || {
+        let mut table = Table::default();
+        for message in reader.incoming_messages() {
+            match message {
+                Ok(OwnedMessage::Text(text)) => table.receive(&text),
+                Ok(OwnedMessage::Ping(data)) => {
+                    let _ = pong.lock().unwrap().send_message(&OwnedMessage::Pong(data));
+                }
+                Ok(OwnedMessage::Close(reason)) => {
+                    match reason {
+                        Some(reason) if !reason.reason.is_empty() => println!("Disconnected: {}", reason.reason),
+                        _ => println!("Disconnected"),
+                    }
+                    break;
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("Connection lost: {e}");
+                    break;
+                }
+            }
+        }
+        exit(0);
+    });
+
+    println!("{HELP}");
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "q" => break,
+            "?" => println!("{HELP}"),
+            line => for text in action(line) {
+                send(&socket, text);
+            },
+        }
+    }
+    let _ = socket.lock().unwrap().send_message(&OwnedMessage::Close(None));
+}
@@ -0,0 +1,316 @@
+//! Puts a running server under load to see how many sessions it can keep up with
+//!
+//! Every simulated client hosts a Blackjack session of its own and plays rounds of random legal moves,
+//! optionally with spectators joining it by code. Each move is timed until the server answers with the next `STATUS`.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+use websocket::sync::Client;
+use websocket::{ClientBuilder, OwnedMessage, WebSocketError};
+
+const USAGE: &str = "\
+Usage: loadtest [OPTIONS]
+
+Options:
+    --server URL        the server's websocket endpoint (default ws://127.0.0.1:8000/ws)
+    --clients N         clients hosting a session each (default 100)
+    --rounds N          rounds every client plays (default 20)
+    --spectators N      spectators joining every session (default 0)
+    --ramp-up MS        milliseconds between starting clients (default 10)";
+
+/// How long to wait for the server before a client gives up
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How often spectators check whether the session they're watching is done with
+const SPECTATOR_POLL: Duration = Duration::from_secs(1);
+
+struct Config {
+    server: String,
+    clients: usize,
+    rounds: usize,
+    spectators: usize,
+    ramp_up: Duration,
+}
+
+impl Config {
+    fn from_args() -> Result<Self, String> {
+        let mut config = Config {
+            server: "ws://127.0.0.1:8000/ws".to_owned(),
+            clients: 100,
+            rounds: 20,
+            spectators: 0,
+            ramp_up: Duration::from_millis(10),
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                println!("{USAGE}");
+                exit(0);
+            }
+            let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+            let invalid = || format!("invalid value for {arg}: {value}");
+            match &*arg {
+                "--server" => config.server = value,
+                "--clients" => config.clients = value.parse().map_err(|_| invalid())?,
+                "--rounds" => config.rounds = value.parse().map_err(|_| invalid())?,
+                "--spectators" => config.spectators = value.parse().map_err(|_| invalid())?,
+                "--ramp-up" => config.ramp_up = Duration::from_millis(value.parse().map_err(|_| invalid())?),
+                _ => return Err(format!("unknown option {arg}")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// What a client (or all of them) saw
+#[derive(Debug, Default)]
+struct Report {
+    connects: Vec<Duration>,
+    /// From sending a move to the server's answer
+    latencies: Vec<Duration>,
+    rounds: usize,
+    messages: usize,
+    /// `ERROR` messages by text
+    errors: BTreeMap<String, usize>,
+    /// How connections were closed, by close code or what went wrong
+    closes: BTreeMap<String, usize>,
+}
+
+impl Report {
+    fn merge(&mut self, other: Report) {
+        self.connects.extend(other.connects);
+        self.latencies.extend(other.latencies);
+        self.rounds += other.rounds;
+        self.messages += other.messages;
+        for (error, n) in other.errors {
+            *self.errors.entry(error).or_default() += n;
+        }
+        for (close, n) in other.closes {
+            *self.closes.entry(close).or_default() += n;
+        }
+    }
+    fn close(&mut self, how: impl Into<String>) {
+        *self.closes.entry(how.into()).or_default() += 1;
+    }
+}
+
+/// A connection ended before the client was done
+struct Closed(String);
+
+struct Connection {
+    client: Client<TcpStream>,
+}
+
+impl Connection {
+    fn open(server: &str, report: &mut Report) -> Result<Self, Closed> {
+        let start = Instant::now();
+        let client = ClientBuilder::new(server)
+            .map_err(|e| Closed(format!("invalid address: {e}")))?
+            .connect_insecure()
+            .map_err(|e| Closed(format!("connect failed: {e}")))?;
+        report.connects.push(start.elapsed());
+        let conn = Connection { client };
+        conn.set_timeout(TIMEOUT)?;
+        Ok(conn)
+    }
+    fn set_timeout(&self, timeout: Duration) -> Result<(), Closed> {
+        self.client.stream_ref().set_read_timeout(Some(timeout)).map_err(|e| Closed(e.to_string()))
+    }
+    fn send(&mut self, text: String) -> Result<(), Closed> {
+        self.client.send_message(&OwnedMessage::Text(text)).map_err(|e| Closed(format!("send failed: {e}")))
+    }
+    /// The next text message, answering pings on the way
+    fn recv(&mut self, report: &mut Report) -> Result<String, Closed> {
+        self.try_recv(report)?.ok_or_else(|| Closed("timed out".to_owned()))
+    }
+    /// Like [`Connection::recv`], but a read timeout isn't an error
+    fn try_recv(&mut self, report: &mut Report) -> Result<Option<String>, Closed> {
+        loop {
+            match self.client.recv_message() {
+                Ok(OwnedMessage::Text(text)) => {
+                    report.messages += 1;
+                    if let Some(error) = text.strip_prefix("ERROR ") {
+                        *report.errors.entry(error.to_owned()).or_default() += 1;
+                    }
+                    return Ok(Some(text));
+                }
+                Ok(OwnedMessage::Ping(data)) => {
+                    let _ = self.client.send_message(&OwnedMessage::Pong(data));
+                }
+                Ok(OwnedMessage::Close(data)) => {
+                    return Err(Closed(data.map_or("closed".to_owned(), |d| format!("closed {}", d.status_code))));
+                }
+                Ok(_) => (),
+                Err(WebSocketError::IoError(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(Closed(format!("receive failed: {e}"))),
+            }
+        }
+    }
+    /// Reads until a message the predicate likes comes along
+    fn wait_for(&mut self, report: &mut Report, mut wanted: impl FnMut(&str) -> bool) -> Result<String, Closed> {
+        loop {
+            let msg = self.recv(report)?;
+            if wanted(&msg) {
+                return Ok(msg);
+            }
+        }
+    }
+    fn close(mut self) {
+        let _ = self.client.send_message(&OwnedMessage::Close(None));
+        let _ = self.client.shutdown();
+    }
+}
+
+/// Hosts a session, lets the spectators in and plays the rounds
+fn host(config: &Config, report: &mut Report) -> Result<(), Closed> {
+    let mut conn = Connection::open(&config.server, report)?;
+    conn.send("HOST BLACKJACK".to_owned())?;
+    let code = conn.wait_for(report, |msg| msg.starts_with("HOST_OK "))?["HOST_OK ".len()..].to_owned();
+
+    let done = Arc::new(AtomicBool::new(false));
+    let spectators: Vec<_> = (0..config.spectators).map(|_| {
+        let server = config.server.clone();
+        let code = code.clone();
+        let done = done.clone();
+        thread::spawn(move || spectate(&server, &code, &done))
+    }).collect();
+
+    let result = play(config, &mut conn, report);
+    conn.close();
+    done.store(true, Ordering::Relaxed);
+    for spectator in spectators {
+        report.merge(spectator.join().unwrap_or_default());
+    }
+    result
+}
+
+fn play(config: &Config, conn: &mut Connection, report: &mut Report) -> Result<(), Closed> {
+    let mut rng = rand::thread_rng();
+    for _ in 0..config.rounds {
+        conn.send("BLACKJACK BET 100".to_owned())?;
+        let mut sent = Instant::now();
+        conn.send("START".to_owned())?;
+        loop {
+            let status = conn.wait_for(report, |msg| msg.starts_with("BLACKJACK STATUS"))?;
+            report.latencies.push(sent.elapsed());
+            let flags: Vec<&str> = status.split(' ').skip(2).collect();
+            if flags.contains(&"N") {
+                break;
+            }
+            let moves: Vec<&str> = flags.iter().filter_map(|&flag| match flag {
+                "H" => Some("HIT"),
+                "S" => Some("STAND"),
+                "D" => Some("DOUBLEDOWN"),
+                "U" => Some("SURRENDER"),
+                _ => None,
+            }).collect();
+            let Some(&action) = moves.choose(&mut rng) else {
+                break;
+            };
+            sent = Instant::now();
+            conn.send(format!("BLACKJACK {action}"))?;
+        }
+        report.rounds += 1;
+    }
+    Ok(())
+}
+
+/// Watches the session until its host is done
+fn spectate(server: &str, code: &str, done: &AtomicBool) -> Report {
+    let mut report = Report::default();
+    match watch(server, code, done, &mut report) {
+        Ok(()) => report.close("finished"),
+        Err(Closed(how)) => report.close(how),
+    }
+    report
+}
+
+fn watch(server: &str, code: &str, done: &AtomicBool, report: &mut Report) -> Result<(), Closed> {
+    let mut conn = Connection::open(server, report)?;
+    conn.set_timeout(SPECTATOR_POLL)?;
+    conn.send(format!("SPECTATE {code}"))?;
+    while !done.load(Ordering::Relaxed) {
+        conn.try_recv(report)?;
+    }
+    conn.close();
+    Ok(())
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+fn print_latencies(label: &str, durations: &mut [Duration]) {
+    durations.sort_unstable();
+    println!(
+        "{label:<16}p50 {:>9.2?}  p90 {:>9.2?}  p99 {:>9.2?}  max {:>9.2?}",
+        percentile(durations, 0.5),
+        percentile(durations, 0.9),
+        percentile(durations, 0.99),
+        durations.last().copied().unwrap_or_default(),
+    );
+}
+
+fn main() {
+    let config = Config::from_args().unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        exit(2);
+    });
+
+    let start = Instant::now();
+    let clients: Vec<_> = (0..config.clients).map(|_| {
+        thread::sleep(config.ramp_up);
+        let config = Config { server: config.server.clone(), ..config };
+        thread::spawn(move || {
+            let mut report = Report::default();
+            match host(&config, &mut report) {
+                Ok(()) => report.close("finished"),
+                Err(Closed(how)) => report.close(how),
+            }
+            report
+        })
+    }).collect();
+
+    let mut report = Report::default();
+    for client in clients {
+        match client.join() {
+            Ok(client) => report.merge(client),
+            Err(_) => report.close("client panicked"),
+        }
+    }
+    let elapsed = start.elapsed();
+
+    println!("{} clients, {} spectators each, against {}", config.clients, config.spectators, config.server);
+    println!("Ran for {elapsed:.2?}");
+    println!();
+    println!("Rounds          {} ({:.1}/s)", report.rounds, report.rounds as f64 / elapsed.as_secs_f64());
+    println!("Moves           {} ({:.1}/s)", report.latencies.len(), report.latencies.len() as f64 / elapsed.as_secs_f64());
+    println!("Messages read   {} ({:.1}/s)", report.messages, report.messages as f64 / elapsed.as_secs_f64());
+    println!();
+    print_latencies("Connecting", &mut report.connects);
+    print_latencies("Move answered", &mut report.latencies);
+    println!();
+    println!("Connections ended:");
+    for (how, n) in &report.closes {
+        println!("  {n:>7}  {how}");
+    }
+    if !report.errors.is_empty() {
+        println!("Errors from the server:");
+        for (error, n) in &report.errors {
+            println!("  {n:>7}  {error}");
+        }
+    }
+}
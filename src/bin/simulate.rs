@@ -0,0 +1,316 @@
+//! Plays Blackjack headlessly to find out what a rule set is worth to the house
+//!
+//! The rounds are played like at the table: one deck, shuffled anew once fewer than 20 cards are left,
+//! naturals paying 3 to 2, doubling and surrendering on the first two cards and no splits.
+//! Every round is a bet of one unit, so the house edge is the player's average loss per round.
+
+use std::env;
+use std::process::exit;
+use std::thread;
+use std::time::Instant;
+
+use fellestrekk::card::{Card, Deck};
+use fellestrekk::dealer::Dealer;
+use fellestrekk::hand::{BlackjackExt, Hand};
+use fellestrekk::strategy::{self, Options, Play};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const USAGE: &str = "\
+Usage: simulate [OPTIONS]
+
+Options:
+    --rounds N        rounds to play in total (default 1000000)
+    --threads N       threads to play them on (default one per core)
+    --dealer RULE     H17 or S17 (default H17)
+    --strategy NAME   basic, dealer or never-bust (default basic)
+    --seed N          plays the same rounds every time for the same seed and thread count";
+
+/// How the player decides what to do with their hand
+trait Strategy: Sync {
+    fn play(&self, dealer: &Dealer, hand: &Hand, up_card: Card, options: Options) -> Play;
+}
+
+/// The chart in [`strategy`], as the hints at the table give it
+struct Basic;
+
+impl Strategy for Basic {
+    fn play(&self, dealer: &Dealer, hand: &Hand, up_card: Card, options: Options) -> Play {
+        strategy::advise(dealer, hand, up_card, options)
+    }
+}
+
+/// Plays by the dealer's rules
+struct MimicDealer;
+
+impl Strategy for MimicDealer {
+    fn play(&self, dealer: &Dealer, hand: &Hand, _: Card, _: Options) -> Play {
+        if dealer.hits(hand) { Play::Hit } else { Play::Stand }
+    }
+}
+
+/// Only hits when no card can bust the hand
+struct NeverBust;
+
+impl Strategy for NeverBust {
+    fn play(&self, _: &Dealer, hand: &Hand, _: Card, _: Options) -> Play {
+        if hand.is_soft() || hand.value() < 12 { Play::Hit } else { Play::Stand }
+    }
+}
+
+fn strategy_by_name(name: &str) -> Option<&'static dyn Strategy> {
+    match name {
+        "basic" => Some(&Basic),
+        "dealer" => Some(&MimicDealer),
+        "never-bust" => Some(&NeverBust),
+        _ => None,
+    }
+}
+
+struct Config {
+    rounds: u64,
+    threads: usize,
+    dealer: Dealer,
+    strategy_name: String,
+    strategy: &'static dyn Strategy,
+    seed: Option<u64>,
+}
+
+impl Config {
+    fn from_args() -> Result<Self, String> {
+        let mut config = Config {
+            rounds: 1_000_000,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            dealer: Dealer::h17(),
+            strategy_name: "basic".to_owned(),
+            strategy: &Basic,
+            seed: None,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                println!("{USAGE}");
+                exit(0);
+            }
+            let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+            let invalid = || format!("invalid value for {arg}: {value}");
+            match &*arg {
+                "--rounds" => config.rounds = value.parse().map_err(|_| invalid())?,
+                "--threads" => config.threads = value.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?,
+                "--dealer" => config.dealer = match &*value {
+                    "H17" => Dealer::h17(),
+                    "S17" => Dealer::s17(),
+                    _ => return Err(invalid()),
+                },
+                "--strategy" => {
+                    config.strategy = strategy_by_name(&value).ok_or_else(invalid)?;
+                    config.strategy_name = value;
+                }
+                "--seed" => config.seed = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(format!("unknown option {arg}")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// What happened over a number of rounds, in units bet
+#[derive(Debug, Default, Clone)]
+struct Tally {
+    rounds: u64,
+    net: f64,
+    net_squared: f64,
+    wins: u64,
+    losses: u64,
+    pushes: u64,
+    naturals: u64,
+    doubles: u64,
+    surrenders: u64,
+    player_busts: u64,
+    dealer_busts: u64,
+    /// How often the dealer ended on 17 through 21, then on a bust
+    dealer_totals: [u64; 6],
+}
+
+impl Tally {
+    fn merge(&mut self, other: &Tally) {
+        self.rounds += other.rounds;
+        self.net += other.net;
+        self.net_squared += other.net_squared;
+        self.wins += other.wins;
+        self.losses += other.losses;
+        self.pushes += other.pushes;
+        self.naturals += other.naturals;
+        self.doubles += other.doubles;
+        self.surrenders += other.surrenders;
+        self.player_busts += other.player_busts;
+        self.dealer_busts += other.dealer_busts;
+        for (total, other) in self.dealer_totals.iter_mut().zip(other.dealer_totals) {
+            *total += other;
+        }
+    }
+    fn rate(&self, n: u64) -> f64 {
+        100. * n as f64 / self.rounds as f64
+    }
+}
+
+/// A deck that's shuffled anew when it runs low, as the table does
+struct Shoe {
+    deck: Deck,
+    rng: StdRng,
+}
+
+impl Shoe {
+    fn new(rng: StdRng) -> Self {
+        Shoe { deck: Deck::empty(), rng }
+    }
+    fn reshuffle_if_low(&mut self) {
+        if self.deck.size() < 20 {
+            self.deck = Deck::new_standard();
+            self.deck.shuffle_seeded(self.rng.gen());
+        }
+    }
+    fn draw(&mut self) -> Card {
+        self.deck.draw_one().expect("a round never uses 20 cards from one deck")
+    }
+}
+
+fn play_round(shoe: &mut Shoe, dealer: &Dealer, strategy: &dyn Strategy, tally: &mut Tally) {
+    shoe.reshuffle_if_low();
+    let (down_player, down_dealer, open_player, open_dealer) = (shoe.draw(), shoe.draw(), shoe.draw(), shoe.draw());
+    let mut player_hand = Hand::new([down_player, open_player]);
+    let mut dealer_hand = Hand::new([down_dealer, open_dealer]);
+
+    let mut bet = 1.;
+    let mut surrendered = false;
+    while !player_hand.is_bust() && player_hand.value() < 21 {
+        let mut options = Options::for_hand(&player_hand);
+        // Splitting isn't dealt at the table yet
+        options.split = false;
+        match strategy.play(dealer, &player_hand, open_dealer, options) {
+            Play::Hit | Play::Split => player_hand.add_card(shoe.draw()),
+            Play::Stand => break,
+            Play::DoubleDown if options.double => {
+                bet *= 2.;
+                tally.doubles += 1;
+                player_hand.add_card(shoe.draw());
+                break;
+            }
+            Play::DoubleDown => player_hand.add_card(shoe.draw()),
+            Play::Surrender if options.surrender => {
+                surrendered = true;
+                tally.surrenders += 1;
+                break;
+            }
+            Play::Surrender => break,
+        }
+    }
+
+    // The dealer plays out their hand whatever the player did
+    while dealer.hits(&dealer_hand) {
+        dealer_hand.add_card(shoe.draw());
+    }
+    match dealer_hand.value() {
+        value @ 17..=21 => tally.dealer_totals[value as usize - 17] += 1,
+        22.. => tally.dealer_totals[5] += 1,
+        _ => unreachable!("the dealer stands on 17 or more"),
+    }
+    tally.dealer_busts += dealer_hand.is_bust() as u64;
+    tally.player_busts += player_hand.is_bust() as u64;
+    tally.naturals += player_hand.is_natural() as u64;
+
+    use std::cmp::Ordering::*;
+    let net = match player_hand.cmp(&dealer_hand) {
+        _ if surrendered => {
+            tally.losses += 1;
+            -bet / 2.
+        }
+        Greater => {
+            tally.wins += 1;
+            if player_hand.is_natural() { bet * 1.5 } else { bet }
+        }
+        Equal => {
+            tally.pushes += 1;
+            0.
+        }
+        Less => {
+            tally.losses += 1;
+            -bet
+        }
+    };
+    tally.rounds += 1;
+    tally.net += net;
+    tally.net_squared += net * net;
+}
+
+fn simulate(config: &Config) -> Tally {
+    let threads = config.threads as u64;
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..threads).map(|i| {
+            // The rounds are shared out as evenly as they go
+            let rounds = config.rounds / threads + (i < config.rounds % threads) as u64;
+            let rng = match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(i)),
+                None => StdRng::from_entropy(),
+            };
+            s.spawn(move || {
+                let mut shoe = Shoe::new(rng);
+                let mut tally = Tally::default();
+                for _ in 0..rounds {
+                    play_round(&mut shoe, &config.dealer, config.strategy, &mut tally);
+                }
+                tally
+            })
+        }).collect();
+
+        workers.into_iter().fold(Tally::default(), |mut total, worker| {
+            total.merge(&worker.join().expect("simulation thread panicked"));
+            total
+        })
+    })
+}
+
+fn report(config: &Config, tally: &Tally) {
+    let n = tally.rounds as f64;
+    let mean = tally.net / n;
+    let variance = tally.net_squared / n - mean * mean;
+    let std_error = (variance / n).sqrt();
+
+    let dealer = if config.dealer.hits_soft_17() { "H17" } else { "S17" };
+    println!("{} rounds, dealer {dealer}, {} strategy", tally.rounds, config.strategy_name);
+    println!();
+    println!("House edge     {:+.3}% ± {:.3}%", -100. * mean, 196. * std_error);
+    println!("Variance       {variance:.4} (standard deviation {:.4})", variance.sqrt());
+    println!();
+    println!("Wins           {:6.2}%", tally.rate(tally.wins));
+    println!("Pushes         {:6.2}%", tally.rate(tally.pushes));
+    println!("Losses         {:6.2}%", tally.rate(tally.losses));
+    println!("Naturals       {:6.2}%", tally.rate(tally.naturals));
+    println!("Doubles        {:6.2}%", tally.rate(tally.doubles));
+    println!("Surrenders     {:6.2}%", tally.rate(tally.surrenders));
+    println!("Player busts   {:6.2}%", tally.rate(tally.player_busts));
+    println!("Dealer busts   {:6.2}%", tally.rate(tally.dealer_busts));
+    println!();
+    println!("Dealer's final total:");
+    for (i, &count) in tally.dealer_totals.iter().enumerate() {
+        let label = if i == 5 { "Bust".to_owned() } else { (17 + i).to_string() };
+        println!("  {label:<5}        {:6.2}%", tally.rate(count));
+    }
+}
+
+fn main() {
+    let config = Config::from_args().unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        exit(2);
+    });
+    if config.rounds == 0 {
+        eprintln!("Nothing to simulate");
+        exit(2);
+    }
+
+    let start = Instant::now();
+    let tally = simulate(&config);
+    report(&config, &tally);
+    eprintln!();
+    eprintln!("Played on {} threads in {:.2?}", config.threads, start.elapsed());
+}
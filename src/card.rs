@@ -1,10 +1,12 @@
 use std::fmt::{Display, self};
+use std::str::FromStr;
 
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, SeedableRng};
 
 #[repr(transparent)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Card(u8);
 
 impl Card {
@@ -76,6 +78,38 @@ impl Display for Card {
     }
 }
 
+/// Reads a card the way it's displayed, like `♠K`
+impl FromStr for Card {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let suit = match chars.next().ok_or(())? {
+            '♣' => Suit::Clubs,
+            '♥' => Suit::Hearts,
+            '♠' => Suit::Spades,
+            '♦' => Suit::Diamonds,
+            _ => return Err(()),
+        };
+        let rank = match chars.as_str() {
+            "K" => Rank::King,
+            "Q" => Rank::Queen,
+            "J" => Rank::Jack,
+            "10" => Rank::Ten,
+            "9" => Rank::Nine,
+            "8" => Rank::Eight,
+            "7" => Rank::Seven,
+            "6" => Rank::Six,
+            "5" => Rank::Five,
+            "4" => Rank::Four,
+            "3" => Rank::Three,
+            "2" => Rank::Two,
+            "A" => Rank::Ace,
+            _ => return Err(()),
+        };
+        Ok(Card::new((suit, rank)))
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Suit {
@@ -103,7 +137,7 @@ pub enum Rank {
     Ace = 0,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Deck {
     cards: Vec<Card>,
 }
@@ -122,13 +156,68 @@ impl Deck {
     pub fn shuffle(&mut self) {
         self.cards.shuffle(&mut thread_rng())
     }
+    /// Shuffles the same way every time for the same seed
+    pub fn shuffle_seeded(&mut self, seed: u64) {
+        self.cards.shuffle(&mut StdRng::seed_from_u64(seed))
+    }
     pub fn draw_one(&mut self) -> Option<Card> {
         self.cards.pop()
     }
     pub fn put_in_back(&mut self, card: Card) {
         self.cards.insert(0, card);
     }
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
     pub fn size(&self) -> usize {
         self.cards.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_deck_has_every_card_once() {
+        let deck = Deck::new_standard();
+        assert_eq!(deck.size(), 52);
+        let mut cards: Vec<u8> = deck.cards().iter().map(|c| c.into_u8()).collect();
+        cards.sort_unstable();
+        cards.dedup();
+        assert_eq!(cards.len(), 52);
+    }
+
+    #[test]
+    fn seeded_shuffles_repeat() {
+        let mut a = Deck::new_standard();
+        let mut b = Deck::new_standard();
+        a.shuffle_seeded(42);
+        b.shuffle_seeded(42);
+        assert_eq!(a, b);
+        b.shuffle_seeded(43);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn draws_from_the_top_and_puts_in_the_back() {
+        let mut deck = Deck::new_standard();
+        let top = *deck.cards().last().unwrap();
+        assert_eq!(deck.draw_one(), Some(top));
+        assert_eq!(deck.size(), 51);
+        deck.put_in_back(top);
+        assert_eq!(deck.cards()[0], top);
+        assert_eq!(Deck::empty().draw_one(), None);
+    }
+
+    #[test]
+    fn cards_parse_as_displayed() {
+        for card in Deck::new_standard().cards() {
+            assert_eq!(card.to_string().parse(), Ok(*card));
+        }
+        assert_eq!("♠K".parse(), Ok(Card::new((Suit::Spades, Rank::King))));
+        assert_eq!("♥10".parse(), Ok(Card::new((Suit::Hearts, Rank::Ten))));
+        assert_eq!("K".parse::<Card>(), Err(()));
+        assert_eq!("♠1".parse::<Card>(), Err(()));
+    }
+}
@@ -0,0 +1,153 @@
+//! Card counting systems
+//!
+//! Every card seen is tagged with a value and the running count is their sum.
+//! Balanced systems divide it by the decks left to get the true count,
+//! unbalanced ones start off the top and are played off the running count alone.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::card::{Card, Rank};
+
+const DECK_SIZE: f64 = 52.;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CountSystem {
+    HiLo,
+    /// Knock-out, unbalanced
+    Ko,
+    OmegaII,
+}
+
+impl CountSystem {
+    pub const ALL: [CountSystem; 3] = [CountSystem::HiLo, CountSystem::Ko, CountSystem::OmegaII];
+
+    pub fn tag(self, card: Card) -> i32 {
+        use Rank::*;
+        let rank = card.suit_rank().1;
+        match self {
+            CountSystem::HiLo => match rank {
+                Two | Three | Four | Five | Six => 1,
+                Seven | Eight | Nine => 0,
+                Ten | Jack | Queen | King | Ace => -1,
+            },
+            CountSystem::Ko => match rank {
+                Two | Three | Four | Five | Six | Seven => 1,
+                Eight | Nine => 0,
+                Ten | Jack | Queen | King | Ace => -1,
+            },
+            CountSystem::OmegaII => match rank {
+                Two | Three | Seven => 1,
+                Four | Five | Six => 2,
+                Eight | Ace => 0,
+                Nine => -1,
+                Ten | Jack | Queen | King => -2,
+            },
+        }
+    }
+    /// Whether a full shoe adds up to nothing, so there's a true count
+    pub fn is_balanced(self) -> bool {
+        self != CountSystem::Ko
+    }
+    /// Where the running count starts off when the shoe is shuffled
+    pub fn initial_count(self, decks: u32) -> i32 {
+        match self {
+            CountSystem::Ko => 4 - 4 * decks as i32,
+            _ => 0,
+        }
+    }
+}
+
+impl Display for CountSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CountSystem::HiLo => write!(f, "HILO"),
+            CountSystem::Ko => write!(f, "KO"),
+            CountSystem::OmegaII => write!(f, "OMEGA2"),
+        }
+    }
+}
+
+impl FromStr for CountSystem {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HILO" => Ok(CountSystem::HiLo),
+            "KO" => Ok(CountSystem::Ko),
+            "OMEGA2" => Ok(CountSystem::OmegaII),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The count of the cards seen since the shoe was shuffled
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Count {
+    pub system: CountSystem,
+    pub running: i32,
+    /// Cards seen so far
+    pub seen: u32,
+}
+
+impl Count {
+    pub fn new(system: CountSystem, decks: u32) -> Self {
+        Count { system, running: system.initial_count(decks), seen: 0 }
+    }
+    pub fn see(&mut self, card: Card) {
+        self.running += self.system.tag(card);
+        self.seen += 1;
+    }
+    /// The running count per deck left, for balanced systems
+    pub fn true_count(&self, cards_left: usize) -> Option<f64> {
+        if !self.system.is_balanced() || cards_left == 0 {
+            return None;
+        }
+        Some(self.running as f64 / (cards_left as f64 / DECK_SIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Deck;
+
+    fn count_deck(system: CountSystem) -> Count {
+        let mut count = Count::new(system, 1);
+        let mut deck = Deck::new_standard();
+        while let Some(card) = deck.draw_one() {
+            count.see(card);
+        }
+        count
+    }
+
+    #[test]
+    fn knock_out_starts_below_zero_for_a_shoe() {
+        assert_eq!(Count::new(CountSystem::Ko, 1).running, 0);
+        assert_eq!(Count::new(CountSystem::Ko, 6).running, -20);
+        assert_eq!(Count::new(CountSystem::HiLo, 6).running, 0);
+    }
+
+    #[test]
+    fn balanced_systems_add_up_to_nothing_over_a_deck() {
+        for system in [CountSystem::HiLo, CountSystem::OmegaII] {
+            let count = count_deck(system);
+            assert_eq!((count.running, count.seen), (0, 52), "{system}");
+        }
+        assert_eq!(count_deck(CountSystem::Ko).running, 4);
+    }
+
+    #[test]
+    fn true_count_divides_by_the_decks_left() {
+        let mut count = Count::new(CountSystem::HiLo, 6);
+        for card in ["♠2", "♥3", "♦4", "♣5"] {
+            count.see(card.parse().unwrap());
+        }
+        assert_eq!(count.true_count(104), Some(2.));
+    }
+
+    #[test]
+    fn no_true_count_for_unbalanced_systems_or_an_empty_shoe() {
+        assert_eq!(Count::new(CountSystem::Ko, 6).true_count(104), None);
+        assert_eq!(Count::new(CountSystem::HiLo, 6).true_count(0), None);
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+
+use crate::card::Card;
 use crate::hand::Hand;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Dealer {
     /// If false, stands on soft 17, if true, hits on soft 17
     pub (in super) hit_soft_17: bool,
@@ -16,7 +19,209 @@ impl Dealer {
         Dealer { hit_soft_17: true }
     }
     
+    pub fn hits_soft_17(&self) -> bool {
+        self.hit_soft_17
+    }
     pub fn hits(&self, dealer_hand: &Hand) -> bool {
         dealer_hand.value() < 17 || (self.hit_soft_17 && dealer_hand.value() == 17 && dealer_hand.is_soft())
     }
 }
+
+/// How many cards of each Blackjack value are left to draw from
+///
+/// Aces come first, then two through nine, and all ten-valued cards are counted together last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Composition([u16; 10]);
+
+impl Composition {
+    pub fn from_cards(cards: impl IntoIterator<Item = Card>) -> Self {
+        let mut composition = Composition::default();
+        for card in cards {
+            composition.0[index(card)] += 1;
+        }
+        composition
+    }
+    /// Takes a card seen on the table out of what's left, if it was still there
+    pub fn remove(&mut self, card: Card) {
+        let count = &mut self.0[index(card)];
+        *count = count.saturating_sub(1);
+    }
+    pub fn len(&self) -> u16 {
+        self.0.iter().sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn index(card: Card) -> usize {
+    (card.suit_rank().1 as usize).min(9)
+}
+
+/// The chances of how the dealer's hand ends up
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct DealerOdds {
+    /// Finishing on 17 through 21 without a natural
+    pub totals: [f64; 5],
+    pub blackjack: f64,
+    pub bust: f64,
+}
+
+/// The dealer's hand counting aces as one, and whether there is one to count as eleven
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Total {
+    hard: u8,
+    ace: bool,
+}
+
+impl Total {
+    fn add(self, index: usize) -> Self {
+        Total { hard: self.hard + index as u8 + 1, ace: self.ace || index == 0 }
+    }
+    fn value(self) -> u8 {
+        if self.ace && self.hard <= 11 { self.hard + 10 } else { self.hard }
+    }
+    fn is_soft(self) -> bool {
+        self.ace && self.hard <= 11
+    }
+}
+
+type Memo = HashMap<(Composition, Total), DealerOdds>;
+
+impl Dealer {
+    /// The exact chances of every final total with this up-card, the hole card and every draw after coming from the shoe
+    ///
+    /// Positions already worked out are remembered for the rest of the calculation,
+    /// as many orders of drawing lead to the same hand against the same shoe.
+    pub fn odds(&self, up_card: Card, shoe: &Composition) -> DealerOdds {
+        let up = Total { hard: 0, ace: false }.add(index(up_card));
+        let mut memo = Memo::new();
+        let mut odds = DealerOdds::default();
+        let left = shoe.len() as f64;
+        for (i, &count) in shoe.0.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let p = count as f64 / left;
+            let hand = up.add(i);
+            if hand.value() == 21 {
+                odds.blackjack += p;
+                continue;
+            }
+            let mut shoe = *shoe;
+            shoe.0[i] -= 1;
+            odds.add_scaled(&self.play_out(hand, shoe, &mut memo), p);
+        }
+        odds
+    }
+    fn play_out(&self, hand: Total, shoe: Composition, memo: &mut Memo) -> DealerOdds {
+        let value = hand.value();
+        if value > 21 {
+            return DealerOdds { bust: 1., ..Default::default() };
+        }
+        let stands = value > 17 || (value == 17 && !(self.hit_soft_17 && hand.is_soft()));
+        // An empty shoe would be shuffled anew at the table, which is too far to look ahead
+        if stands || shoe.is_empty() {
+            let mut odds = DealerOdds::default();
+            match value {
+                17..=21 => odds.totals[value as usize - 17] = 1.,
+                // Only when the shoe ran dry, which the table never lets happen, so it's put with the lowest total
+                _ => odds.totals[0] = 1.,
+            }
+            return odds;
+        }
+        if let Some(&odds) = memo.get(&(shoe, hand)) {
+            return odds;
+        }
+
+        let mut odds = DealerOdds::default();
+        let left = shoe.len() as f64;
+        for (i, &count) in shoe.0.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let mut next = shoe;
+            next.0[i] -= 1;
+            odds.add_scaled(&self.play_out(hand.add(i), next, memo), count as f64 / left);
+        }
+        memo.insert((shoe, hand), odds);
+        odds
+    }
+}
+
+impl DealerOdds {
+    fn add_scaled(&mut self, other: &DealerOdds, p: f64) {
+        for (total, other) in self.totals.iter_mut().zip(other.totals) {
+            *total += other * p;
+        }
+        self.blackjack += other.blackjack * p;
+        self.bust += other.bust * p;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Deck;
+
+    fn hand(cards: &[&str]) -> Hand {
+        let mut hand = Hand::default();
+        for card in cards {
+            hand.add_card(card.parse().unwrap());
+        }
+        hand
+    }
+
+    #[test]
+    fn hits_below_17() {
+        for dealer in [Dealer::h17(), Dealer::s17()] {
+            assert!(dealer.hits(&hand(&["♠10", "♥6"])));
+            assert!(!dealer.hits(&hand(&["♠10", "♥7"])));
+            assert!(!dealer.hits(&hand(&["♠A", "♥7"])));
+        }
+    }
+
+    #[test]
+    fn soft_17_depends_on_the_rules() {
+        let soft_17 = hand(&["♠A", "♥6"]);
+        assert!(Dealer::h17().hits(&soft_17));
+        assert!(!Dealer::s17().hits(&soft_17));
+    }
+
+    #[test]
+    fn composition_of_a_deck() {
+        let mut shoe = Composition::from_cards(Deck::new_standard().cards().iter().copied());
+        assert_eq!(shoe.len(), 52);
+        assert_eq!(shoe.0[0], 4);
+        assert_eq!(shoe.0[9], 16);
+        let ace = "♠A".parse().unwrap();
+        for _ in 0..5 {
+            shoe.remove(ace);
+        }
+        assert_eq!(shoe.0[0], 0);
+        assert_eq!(shoe.len(), 48);
+    }
+
+    #[test]
+    fn odds_add_up() {
+        let shoe = Composition::from_cards(Deck::new_standard().cards().iter().copied());
+        for up_card in ["♠A", "♠6", "♠K"] {
+            let odds = Dealer::h17().odds(up_card.parse().unwrap(), &shoe);
+            let total: f64 = odds.totals.iter().sum::<f64>() + odds.blackjack + odds.bust;
+            assert!((total - 1.).abs() < 1e-9, "{up_card}: {total}");
+        }
+    }
+
+    #[test]
+    fn odds_of_a_known_shoe() {
+        let card = |c: &str| c.parse::<Card>().unwrap();
+
+        let odds = Dealer::s17().odds(card("♠A"), &Composition::from_cards([card("♠K")]));
+        assert_eq!(odds.blackjack, 1.);
+
+        // A six in the hole makes 16 and the king drawn after busts, a king in the hole makes 20
+        let odds = Dealer::s17().odds(card("♦K"), &Composition::from_cards([card("♠6"), card("♥K")]));
+        assert_eq!(odds.bust, 0.5);
+        assert_eq!(odds.totals, [0., 0., 0., 0.5, 0.]);
+    }
+}
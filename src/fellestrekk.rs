@@ -1,138 +1,131 @@
-use collect_result::CollectResult;
-use rocket::tokio::sync::{RwLock, RwLockReadGuard};
+use rocket::tokio::sync::RwLock;
 use rocket::State;
+use rocket::http::CookieJar;
 
 use rocket::futures::{SinkExt,StreamExt};
 use rocket::tokio::select;
-use rocket::tokio::sync::mpsc::error::SendError;
-use rocket::tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use rocket::tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use rocket::tokio::sync::oneshot;
 use rocket::tokio::time::sleep;
 use rocket_ws::frame::{CloseFrame, CloseCode};
 use rocket_ws::{WebSocket, Channel, stream::DuplexStream, Message};
 
-use std::sync::{Arc, Mutex};
-use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::fmt::{self, Display};
+use std::num::ParseIntError;
 use std::time::Duration;
 use std::borrow::Cow;
 
 use rand::{Rng, thread_rng};
 
-use crate::card::Card;
-use crate::games::{Blackjack, Game};
-
-type SendResult<T> = Result<T, SendError<Command>>;
-
-type Player = UnboundedSender<Command>;
-
-pub struct Session {
-    players: BTreeMap<u32, Player>,
-    pub game: Box<dyn Game + Send + Sync>,
-}
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+use crate::accounts::{self, Accounts};
+use crate::games::{DynGame, GameId, RoundResult, Setting};
+use crate::storage::Storage;
+
+mod admin;
+mod bots;
+mod chat;
+mod history;
+mod lobby;
+mod matchmaking;
+mod metrics;
+mod session;
+mod stats;
+mod timer;
+
+pub use self::admin::{admin_close, admin_notice, admin_session, admin_sessions, AdminToken, SessionSummary};
+pub use self::bots::{Bot, Difficulty};
+pub use self::chat::{ChatFilter, Notice, Outcome};
+pub use self::history::Round;
+pub use self::lobby::{lobby_ws, Listing, Lobby};
+use self::matchmaking::{Matchmaker, Waiting};
+pub use self::metrics::{prometheus, Closer, Metrics};
+pub use self::session::{JoinError, NameError, Session, SessionHandle};
+pub use self::stats::{PlayerStats, Standing, Tally};
+pub(crate) use self::session::valid_name;
+use self::session::{Snapshot, HISTORY, SNAPSHOTS};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct PlayerId(u32);
 impl PlayerId {
     pub const HOST: Self = Self(0);
 }
-
-impl Session {
-    #[inline]
-    fn new(host: Player, game: Box<dyn Game + Send + Sync>) -> Self {
-        let mut players = BTreeMap::new();
-        players.insert(0, host);
-        Session {
-            players,
-            game,
-        }
-    }
-    fn send_command(&self, cmd: Command) -> SendResult<()> {
-        for player in self.players.values() {
-            player.send(cmd.clone())?;
-        }
-        Ok(())
-    }
-    /// Whether any player has left (which should end the session)
-    fn is_empty(&self) -> bool {
-        self.players.is_empty()
+impl FromStr for PlayerId {
+    type Err = ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(PlayerId)
     }
-    fn join(&mut self, player: Player) -> Option<PlayerId> {
-        if self.game.has_space() {
-            let new_key = 1 + *self.players.last_key_value().unwrap().0;
-            self.players.insert(new_key, player);
-            Some(PlayerId(new_key))
-        } else {
-            None
-        }
+}
+impl Display for PlayerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
+/// The close code counted for a close frame without one
+const NO_STATUS: u16 = 1005;
+/// Longest reason (in bytes) that fits in a WebSocket close frame
+const MAX_CLOSE_REASON: usize = 123;
+
 fn gen_game_code() -> u16 {
     thread_rng().gen()
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum GameId {
-    Blackjack,
-    Chatroom,
-}
-
-impl FromStr for GameId {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "BLACKJACK" => GameId::Blackjack,
-            "CHATROOM" => GameId::Chatroom,
-            _ => return Err(()),
-        })
-    }
-}
-impl Display for GameId {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            GameId::Blackjack => write!(f, "BLACKJACK"),
-            GameId::Chatroom => write!(f, "CHATROOM"),
-        }
-    }
-}
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
-    Host(GameId),
-    Join(u16),
+    Host(GameId, Option<String>),
+    /// Session code, name and password
+    Join(u16, Option<String>, Option<String>),
+    Spectate(u16, Option<String>, Option<String>),
+    /// Asks for a seat at any game of this kind with these settings, answered with `JOIN_OK` once there is one
+    ///
+    /// Sent as `QUEUE <game> [<key>=<value>...] [name]`.
+    Queue(GameId, Vec<(String, String)>, Option<String>),
     HostOk(u16),
     JoinOk(u16, Option<String>),
+    SpectateOk(u16, String),
+    /// Host asks to start the game (or its next round)
     Start,
-    Draw,
-    PlayerDraw(PlayerId, Card),
-    DeckSize(u8),
-
-    Bet(u32),
-    SendMoney(u32),
-    TakeMoney(u32),
-    // Blackjack
-    ValueUpdate(Option<PlayerId>, u8, bool),
-    DealerDraw(Card),
-    RevealDowns(Card, Vec<Card>),
-    DownCard(Card),
-    Status{
-        hit: bool,
-        stand: bool,
-        double: bool,
-        surrender: bool,
-        split: bool,
-        new_game: bool,
-    },
-    Stand,
-    Hit,
-    DoubleDown,
-    Surrender,
-    Split,
+    /// Host changes one of the game's settings, see [`Setting`]
+    Set(String, String),
+    Setting(Setting),
+    /// The game has ended
+    Finished,
+    /// The round with this number is over, its history can be fetched
+    RoundOver(u32),
+    /// How everyone at the table has done this session, a player sends it empty to ask
+    Scores(Vec<(PlayerId, Tally)>),
+    /// An action or event in a game's own namespace, sent as `<GAME> <payload>`
+    ///
+    /// The payload is parsed by the game itself, see [`Game::Action`](crate::games::Game::Action).
+    Game(GameId, String),
     // Misc
+    Name(String),
+    Players(Vec<(PlayerId, String)>),
+    Watchers(Vec<(PlayerId, String)>),
+    SpectatorSettings {
+        max: u32,
+        chat: bool,
+    },
+    Error(String),
+    System(Notice),
+    // Moderation
+    HostIs(PlayerId),
+    Kick(PlayerId, String),
+    Mute(PlayerId, bool),
+    Lock(bool),
+    /// Whether the session is listed in the lobby
+    Public(bool),
+    /// Seats a computer-controlled player
+    Bot(Difficulty),
+    Password(Option<String>),
+    TurnTime(u32),
+    /// Whose turn it is and how many seconds they have, `None` when no clock is running
+    Timer(Option<(PlayerId, u32)>),
     ChatMsg(PlayerId, String),
     Chat(String),
-    Win,
-    Lose,
     Nop
 }
 
@@ -140,6 +133,57 @@ impl Command {
     fn into_message(self) -> Message {
         Message::Text(self.to_string())
     } 
+    /// Whether this is a command only the host may send
+    fn is_host_only(&self) -> bool {
+        matches!(self,
+            Command::Kick(..) | Command::Mute(..) | Command::Lock(_) | Command::Password(_)
+            | Command::HostIs(_) | Command::SpectatorSettings { .. } | Command::TurnTime(_)
+            | Command::Start | Command::Set(..) | Command::Public(_) | Command::Bot(_)
+        )
+    }
+}
+
+impl Command {
+    /// The keyword the command is sent with, or the game's name and its own keyword for a game's commands
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Command::Host(..) => "HOST",
+            Command::Join(..) => "JOIN",
+            Command::Spectate(..) => "SPECTATE",
+            Command::Queue(..) => "QUEUE",
+            Command::HostOk(_) => "HOST_OK",
+            Command::JoinOk(..) => "JOIN_OK",
+            Command::SpectateOk(..) => "SPECTATE_OK",
+            Command::Start => "START",
+            Command::Set(..) => "SET",
+            Command::Setting(_) => "SETTING",
+            Command::Finished => "FINISHED",
+            Command::RoundOver(_) => "ROUND_OVER",
+            Command::Scores(_) => "SCORES",
+            Command::Game(game, payload) => game.command_kind(payload),
+            Command::Name(_) => "NAME",
+            Command::Players(_) => "PLAYERS",
+            Command::Watchers(_) => "WATCHERS",
+            Command::SpectatorSettings { .. } => "SPECTATORS",
+            Command::Error(_) => "ERROR",
+            Command::System(_) => "SYSTEM",
+            Command::HostIs(_) => "HOST_IS",
+            Command::Kick(..) => "KICK",
+            Command::Mute(_, true) => "MUTE",
+            Command::Mute(_, false) => "UNMUTE",
+            Command::Lock(true) => "LOCK",
+            Command::Lock(false) => "UNLOCK",
+            Command::Public(true) => "PUBLIC",
+            Command::Public(false) => "PRIVATE",
+            Command::Bot(_) => "BOT",
+            Command::Password(_) => "PASSWORD",
+            Command::TurnTime(_) => "TURNTIME",
+            Command::Timer(_) => "TIMER",
+            Command::ChatMsg(..) => "CHAT_MSG",
+            Command::Chat(_) => "CHAT",
+            Command::Nop => "NOP",
+        }
+    }
 }
 
 impl FromStr for Command {
@@ -147,71 +191,81 @@ impl FromStr for Command {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut split = s.split(' ');
         match split.next().ok_or(())? {
-            "HOST" => Ok(Command::Host(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
-            "JOIN" => Ok(Command::Join(u16::from_str_radix(split.next().ok_or(())?, 16).map_err(|_| ())?)),
+            "HOST" => Ok(Command::Host(
+                split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+                split.next().map(|s| s.to_owned()),
+            )),
+            "JOIN" => Ok(Command::Join(
+                u16::from_str_radix(split.next().ok_or(())?, 16).map_err(|_| ())?,
+                split.next().map(|s| s.to_owned()),
+                split.next().map(|s| s.to_owned()),
+            )),
+            "SPECTATE" => Ok(Command::Spectate(
+                u16::from_str_radix(split.next().ok_or(())?, 16).map_err(|_| ())?,
+                split.next().map(|s| s.to_owned()),
+                split.next().map(|s| s.to_owned()),
+            )),
+            "QUEUE" => {
+                let game = split.next().ok_or(())?.parse()?;
+                let mut settings = Vec::new();
+                let mut name = None;
+                for word in split {
+                    match word.split_once('=') {
+                        Some((key, value)) => settings.push((key.to_owned(), value.to_owned())),
+                        None => name = Some(word.to_owned()),
+                    }
+                }
+                Ok(Command::Queue(game, settings, name))
+            }
+            "SPECTATE_OK" => Ok(Command::SpectateOk(
+                u16::from_str_radix(split.next().ok_or(())?, 16).map_err(|_| ())?,
+                split.next().ok_or(())?.to_owned(),
+            )),
             "HOST_OK" => Ok(Command::HostOk(u16::from_str_radix(split.next().ok_or(())?, 16).map_err(|_| ())?)),
             "JOIN_OK" => Ok(Command::JoinOk(u16::from_str_radix(split.next().ok_or(())?, 16).map_err(|_| ())?, split.next().map(|s| s.to_owned()))),
             "START" => Ok(Command::Start),
-            "BET" => Ok(Command::Bet(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
-            "TAKEMONEY" => Ok(Command::TakeMoney(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
-            "SENDMONEY" => Ok(Command::SendMoney(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
-            "DRAW" => Ok(Command::Draw),
-            "REVEALDOWNS" => Ok(Command::RevealDowns(
-                Card::from_u8(split.next().ok_or(())?.parse().map_err(|_| ())?),
-                split.map(|c| c.parse().map_err(|_| ()).map(Card::from_u8)).collect_result::<Vec<_>>()?,
-            )),
-            "PLAYERDRAW" => Ok(Command::PlayerDraw(
-                PlayerId(u32::from_str(split.next().ok_or(())?).map_err(|_| ())?),
-                Card::from_u8(split.next().ok_or(())?.parse().map_err(|_| ())?)
+            "SET" => Ok(Command::Set(
+                split.next().ok_or(())?.to_owned(),
+                split.next().ok_or(())?.to_owned(),
             )),
-            "DECKSIZE" => Ok(Command::DeckSize(
-                split.next().ok_or(())?.parse().map_err(|_| ())?
+            "SETTING" => Ok(Command::Setting(split.collect::<Vec<&str>>().join(" ").parse()?)),
+            "FINISHED" => Ok(Command::Finished),
+            "ROUND_OVER" => Ok(Command::RoundOver(split.next().ok_or(())?.parse().map_err(|_| ())?)),
+            "SCORES" => Ok(Command::Scores(parse_scores(split)?)),
+            "NAME" => Ok(Command::Name(split.next().ok_or(())?.to_owned())),
+            "PLAYERS" => Ok(Command::Players(parse_roster(split)?)),
+            "WATCHERS" => Ok(Command::Watchers(parse_roster(split)?)),
+            "SPECTATORS" => Ok(Command::SpectatorSettings {
+                max: split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+                chat: split.next() == Some("CHAT"),
+            }),
+            "ERROR" => Ok(Command::Error(
+                split.collect::<Vec<&str>>().join(" ")
             )),
-            "DEALERDRAW" => Ok(Command::DealerDraw(
-                Card::from_u8(split.next().ok_or(())?.parse().map_err(|_| ())?)
+            "SYSTEM" => Ok(Command::System(
+                split.collect::<Vec<&str>>().join(" ").parse()?
             )),
-            "DOWNCARD" => Ok(Command::DownCard(
-                Card::from_u8(split.next().ok_or(())?.parse().map_err(|_| ())?),
+            "HOST_IS" => Ok(Command::HostIs(PlayerId(split.next().ok_or(())?.parse().map_err(|_| ())?))),
+            "KICK" => Ok(Command::Kick(
+                PlayerId(split.next().ok_or(())?.parse().map_err(|_| ())?),
+                split.collect::<Vec<&str>>().join(" ")
             )),
-            "STAND" => Ok(Command::Stand),
-            "HIT" => Ok(Command::Hit),
-            "DOUBLEDOWN" => Ok(Command::DoubleDown),
-            "SURRENDER" => Ok(Command::Surrender),
-            "SPLIT" => Ok(Command::Split),
-            "VALUEUPDATE" => {
-                let mut iter = split.rev();
-                let last = iter.next().ok_or(())?;
-                let soft = last == "soft";
-                let value = if soft {
-                    iter.next().ok_or(())?
-                } else {
-                    last
-                }.parse().map_err(|_| ())?;
-                let pn = iter.next().and_then(|pn| pn.parse().map(PlayerId).ok());
-                Ok(Command::ValueUpdate(pn, value, soft))
-            }
-            "STATUS" => {
-                let iter = split;
-                let mut hit = false;
-                let mut stand = false;
-                let mut double = false;
-                let mut surrender = false;
-                let mut split = false;
-                let mut new_game = false;
-
-                for s in iter {
-                    match s {
-                        "H" => hit = true,
-                        "S" => stand = true,
-                        "D" => double = true,
-                        "U" => surrender = true,
-                        "P" => split = true,
-                        "N" => new_game = true,
-                        _ => return Err(()),
-                    }
-                }
-                Ok(Command::Status { hit, stand, double, surrender, split, new_game })
-            }
+            "MUTE" => Ok(Command::Mute(PlayerId(split.next().ok_or(())?.parse().map_err(|_| ())?), true)),
+            "UNMUTE" => Ok(Command::Mute(PlayerId(split.next().ok_or(())?.parse().map_err(|_| ())?), false)),
+            "LOCK" => Ok(Command::Lock(true)),
+            "UNLOCK" => Ok(Command::Lock(false)),
+            "PUBLIC" => Ok(Command::Public(true)),
+            "PRIVATE" => Ok(Command::Public(false)),
+            "BOT" => Ok(Command::Bot(split.next().map_or(Ok(Difficulty::default()), |s| s.parse())?)),
+            "TURNTIME" => Ok(Command::TurnTime(split.next().ok_or(())?.parse().map_err(|_| ())?)),
+            "TIMER" => Ok(Command::Timer(match split.next() {
+                Some(pid) => Some((
+                    PlayerId(pid.parse().map_err(|_| ())?),
+                    split.next().ok_or(())?.parse().map_err(|_| ())?,
+                )),
+                None => None,
+            })),
+            "PASSWORD" => Ok(Command::Password(split.next().filter(|s| !s.is_empty()).map(|s| s.to_owned()))),
             "CHAT_MSG" => Ok(Command::ChatMsg(
                 PlayerId(u32::from_str(split.next().ok_or(())?).map_err(|_| ())?),
                 split.collect::<Vec<&str>>().join(" ")
@@ -219,222 +273,281 @@ impl FromStr for Command {
             "CHAT" => Ok(Command::Chat(
                 split.collect::<Vec<&str>>().join(" ")
             )),
-            "WIN" => Ok(Command::Win),
-            "LOSE" => Ok(Command::Lose),
             "NOP" => Ok(Command::Nop),
-            _ => Err(())
+            game => Ok(Command::Game(
+                game.parse()?,
+                split.collect::<Vec<&str>>().join(" ")
+            )),
         }
     }
 }
 
+fn parse_roster<'a>(mut split: impl Iterator<Item=&'a str>) -> Result<Vec<(PlayerId, String)>, ()> {
+    let mut players = Vec::new();
+    while let Some(pid) = split.next() {
+        let pid = PlayerId(pid.parse().map_err(|_| ())?);
+        players.push((pid, split.next().ok_or(())?.to_owned()));
+    }
+    Ok(players)
+}
+
+fn parse_scores<'a>(split: impl Iterator<Item=&'a str>) -> Result<Vec<(PlayerId, Tally)>, ()> {
+    let words: Vec<&str> = split.collect();
+    words.chunks(6)
+        .map(|chunk| match chunk {
+            [pid, tally @ ..] => Ok((pid.parse().map_err(|_| ())?, tally.join(" ").parse()?)),
+            [] => Err(()),
+        })
+        .collect()
+}
+
+/// A password can only be given after a name
+fn fmt_credentials(f: &mut fmt::Formatter<'_>, name: &Option<String>, password: &Option<String>) -> fmt::Result {
+    if let Some(name) = name {
+        write!(f, " {name}")?;
+        if let Some(pw) = password {
+            write!(f, " {pw}")?;
+        }
+    }
+    Ok(())
+}
+
+fn fmt_roster(f: &mut fmt::Formatter<'_>, cmd: &str, players: &[(PlayerId, String)]) -> fmt::Result {
+    write!(f, "{cmd}")?;
+    for (pid, name) in players {
+        write!(f, " {} {name}", pid.0)?;
+    }
+    Ok(())
+}
+
 impl Display for Command {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Command::Host(game) => write!(f, "HOST {game}"),
-            Command::Join(c) => write!(f, "JOIN {c:X}"),
-            Command::HostOk(c) => write!(f, "HOST_OK {c:X}"),
-            Command::JoinOk(c, Some(s2)) => write!(f, "JOIN_OK {c:X} {s2}"),
-            Command::JoinOk(c, None) => write!(f, "JOIN_OK {c:X}"),
-            Command::Start => write!(f, "START"),
-            Command::Bet(i) => write!(f, "BET {i}"),
-            Command::TakeMoney(i) => write!(f, "TAKEMONEY {i}"),
-            Command::SendMoney(i) => write!(f, "SENDMONEY {i}"),
-            Command::Draw => write!(f, "DRAW"),
-            Command::PlayerDraw(p, c) => write!(f, "PLAYERDRAW {} {c}", p.0),
-            Command::DeckSize(n) => write!(f, "DECKSIZE {n}"),
-            Command::DealerDraw(c) => write!(f, "DEALERDRAW {c}"),
-            Command::DownCard(c) => {
-                write!(f, "DOWNCARD {c}")?;
-                Ok(())
+            Command::Host(game, Some(name)) => write!(f, "HOST {game} {name}"),
+            Command::Host(game, None) => write!(f, "HOST {game}"),
+            Command::Join(c, name, password) => {
+                write!(f, "JOIN {c:X}")?;
+                fmt_credentials(f, name, password)
             }
-            Command::RevealDowns(c, ps) => {
-                write!(f, "REVEALDOWNS {c}")?;
-                for p in ps {
-                    write!(f, " {p}")?;
-                }
-                Ok(())
+            Command::Spectate(c, name, password) => {
+                write!(f, "SPECTATE {c:X}")?;
+                fmt_credentials(f, name, password)
             }
-            Command::Stand => write!(f, "STAND"),
-            Command::Hit => write!(f, "HIT"),
-            Command::DoubleDown => write!(f, "DOUBLEDOWN"),
-            Command::Surrender => write!(f, "SURRENDER"),
-            Command::Split => write!(f, "SPLIT"),
-            &Command::ValueUpdate(pn, value, soft) => {
-                write!(f, "VALUEUPDATE")?;
-                if let Some(pid) = pn {
-                    write!(f, " {}", pid.0)?;
+            Command::Queue(game, settings, name) => {
+                write!(f, "QUEUE {game}")?;
+                for (key, value) in settings {
+                    write!(f, " {key}={value}")?;
                 }
-                write!(f, " {value}")?;
-                if soft {
-                    write!(f, " soft")?;
+                match name {
+                    Some(name) => write!(f, " {name}"),
+                    None => Ok(()),
                 }
-                Ok(())
             }
-            &Command::Status { hit, stand, double, surrender, split, new_game } => {
-                write!(f, "STATUS")?;
-                if hit {
-                    write!(f, " H")?;
-                }
-                if stand {
-                    write!(f, " S")?;
-                }
-                if double {
-                    write!(f, " D")?;
-                }
-                if surrender {
-                    write!(f, " U")?;
-                }
-                if split {
-                    write!(f, " P")?;
-                }
-                if new_game {
-                    write!(f, " N")?;
+            Command::SpectateOk(c, name) => write!(f, "SPECTATE_OK {c:X} {name}"),
+            Command::HostOk(c) => write!(f, "HOST_OK {c:X}"),
+            Command::JoinOk(c, Some(s2)) => write!(f, "JOIN_OK {c:X} {s2}"),
+            Command::JoinOk(c, None) => write!(f, "JOIN_OK {c:X}"),
+            Command::Start => write!(f, "START"),
+            Command::Set(key, value) => write!(f, "SET {key} {value}"),
+            Command::Setting(setting) => write!(f, "SETTING {setting}"),
+            Command::Finished => write!(f, "FINISHED"),
+            Command::RoundOver(n) => write!(f, "ROUND_OVER {n}"),
+            Command::Scores(scores) => {
+                write!(f, "SCORES")?;
+                for (pid, tally) in scores {
+                    write!(f, " {pid} {tally}")?;
                 }
                 Ok(())
             }
+            Command::Game(game, payload) => write!(f, "{game} {payload}"),
+            Command::Name(name) => write!(f, "NAME {name}"),
+            Command::Players(players) => fmt_roster(f, "PLAYERS", players),
+            Command::Watchers(spectators) => fmt_roster(f, "WATCHERS", spectators),
+            Command::SpectatorSettings { max, chat: true } => write!(f, "SPECTATORS {max} CHAT"),
+            Command::SpectatorSettings { max, chat: false } => write!(f, "SPECTATORS {max}"),
+            Command::Error(e) => write!(f, "ERROR {e}"),
+            Command::System(notice) => write!(f, "SYSTEM {notice}"),
+            Command::HostIs(p) => write!(f, "HOST_IS {}", p.0),
+            Command::Kick(p, reason) => write!(f, "KICK {} {reason}", p.0),
+            Command::Mute(p, true) => write!(f, "MUTE {}", p.0),
+            Command::Mute(p, false) => write!(f, "UNMUTE {}", p.0),
+            Command::Lock(true) => write!(f, "LOCK"),
+            Command::Lock(false) => write!(f, "UNLOCK"),
+            Command::Public(true) => write!(f, "PUBLIC"),
+            Command::Public(false) => write!(f, "PRIVATE"),
+            Command::Bot(difficulty) => write!(f, "BOT {difficulty}"),
+            Command::Password(Some(pw)) => write!(f, "PASSWORD {pw}"),
+            Command::Password(None) => write!(f, "PASSWORD"),
+            Command::TurnTime(s) => write!(f, "TURNTIME {s}"),
+            Command::Timer(Some((p, s))) => write!(f, "TIMER {} {s}", p.0),
+            Command::Timer(None) => write!(f, "TIMER"),
             Command::ChatMsg(p, m) => write!(f, "CHAT_MSG {} {m}", p.0),
             Command::Chat(m) => write!(f, "CHAT {m}"),
-            Command::Win => write!(f, "WIN"),
-            Command::Lose => write!(f, "LOSE"),
             Command::Nop => write!(f, ""),
         }
     }
 }
 
-pub struct CommandQueue<'a> {
-    inner: &'a mut Vec<Command>,
-}
-
-impl<'a> CommandQueue<'a> {
-    fn new<'b: 'a>(inner: &'b mut Vec<Command>) -> CommandQueue<'a> {
-        Self {inner}
-    }
-    pub(crate) fn send(&mut self, cmd: Command) {
-        self.inner.push(cmd);
-    }
-    pub(crate) fn reborrow<'b>(&'b mut self) -> CommandQueue<'b> {
-        CommandQueue {
-            inner: self.inner
-        }
-    }
-}
-
 #[get("/ws")]
-pub fn ws(ws: WebSocket, session_store: &State<SessionStore>) -> Channel<'static> {
+pub fn ws(ws: WebSocket, session_store: &State<SessionStore>, cookies: &CookieJar<'_>) -> Channel<'static> {
     let sessions = session_store.inner().clone();
+    let token = cookies.get(accounts::COOKIE).map(|c| c.value().to_owned());
 
     ws.channel(move |mut stream: DuplexStream| Box::pin(async move {
-        let code: u16;
-        let mut assigned_pid: Option<PlayerId> = None;
-
+        let metrics = &sessions.metrics;
+        let _socket = metrics.socket();
         let (tx, mut rx) = unbounded_channel();
-        let cmd = handle(&mut stream).await?;
-
-        match cmd {
-            Command::Join(the_code) => {
-                code = the_code;
-                let message;
-                if let Some(lock) = sessions.get(code).await {
-                    let mut session = lock.lock().unwrap();
-                    if let Some(pid) = session.join(tx.clone()) {
-                        assigned_pid = Some(pid);
-                        message = Command::JoinOk(code, None).into_message();
-                    } else {
-                        message = Message::Close(Some(CloseFrame {
-                            code: CloseCode::Again,
-                            reason: Cow::Borrowed("Game full")
-                        }));
+        let cmd = handle(&mut stream, metrics).await?;
+        // Results of players with an account are counted there, and they go by its name unless they pick one
+        let account = match &token {
+            Some(token) => sessions.accounts.get(token).await,
+            None => None,
+        };
+        let default_name = account.as_ref().and_then(|a| a.display_name()).map(|n| n.to_owned());
+        let account = account.map(|a| a.token().to_owned());
+
+        let (pid, session) = match cmd.clone() {
+            Command::Join(code, name, password) | Command::Spectate(code, name, password) => {
+                let spectate = matches!(cmd, Command::Spectate(..));
+                let joined = match sessions.get(code).await {
+                    Some(session) => session.join(tx.clone(), name.or(default_name), account, password, spectate).await
+                        .map(|joined| (joined, session)),
+                    None => Err(JoinError::NoSuchGame),
+                };
+                match joined {
+                    Ok(((pid, name), session)) => {
+                        let ok = if spectate {
+                            Command::SpectateOk(code, name)
+                        } else {
+                            Command::JoinOk(code, Some(name))
+                        };
+                        send(&mut stream, metrics, ok).await?;
+                        (pid, session)
+                    }
+                    Err(e) => {
+                        close(&mut stream, metrics, Some(e.close_frame())).await?;
+                        return Ok(());
                     }
-                } else {
-                    message = Message::Close(Some(CloseFrame {
-                        code: CloseCode::Policy,
-                        reason: Cow::Borrowed("No such game")
-                    }));
                 }
-                stream.send(message).await?;
             }
-            Command::Host(_) => {
-                assigned_pid = Some(PlayerId::HOST);
-
-                code = loop {
-                    let code = gen_game_code();
-                    
-                    if !sessions.has(code).await {
-                        break code;
+            Command::Host(game, name) => {
+                let name = name.or(default_name).unwrap_or_else(|| "Player1".to_owned());
+                if !valid_name(&name) {
+                    close(&mut stream, metrics, Some(JoinError::Name(NameError::Invalid).close_frame())).await?;
+                    return Ok(());
+                }
+
+                let session = Session::new(tx.clone(), name, account, sessions.new_game(game), sessions.chat_filter.clone());
+                let (code, session) = session.open(&sessions).await;
+                send(&mut stream, metrics, Command::HostOk(code)).await?;
+                (PlayerId::HOST, session)
+            }
+            Command::Queue(game, settings, name) => {
+                if name.as_deref().is_some_and(|name| !valid_name(name)) {
+                    close(&mut stream, metrics, Some(JoinError::Name(NameError::Invalid).close_frame())).await?;
+                    return Ok(());
+                }
+                let (matched, mut found) = oneshot::channel();
+                let waiting = Waiting { tx: tx.clone(), name: name.or(default_name), account, matched };
+                if let Err(e) = sessions.matchmaker.queue(&sessions, game, settings, waiting).await {
+                    send(&mut stream, metrics, Command::Error(e.to_string())).await?;
+                    close(&mut stream, metrics, None).await?;
+                    return Ok(());
+                }
+                // Anything but closing the connection is ignored while waiting
+                let found = loop {
+                    select! {
+                        found = &mut found => break found,
+                        cmd = handle(&mut stream, metrics) => { cmd?; }
                     }
                 };
-                stream.send(Command::HostOk(code).into_message()).await?;
-                sessions.add(code.clone(), Session::new(tx.clone(), Box::new(Blackjack::new()))).await;
+                let Ok(found) = found else {
+                    close(&mut stream, metrics, Some(JoinError::NoSuchGame.close_frame())).await?;
+                    return Ok(());
+                };
+                send(&mut stream, metrics, Command::JoinOk(found.code, Some(found.name))).await?;
+                (found.pid, found.session)
+            }
+            _ => {
+                close(&mut stream, metrics, Some(CloseFrame {
+                    code: CloseCode::Policy,
+                    reason: Cow::Borrowed("Expected HOST, JOIN, SPECTATE or QUEUE"),
+                })).await?;
+                return Ok(());
             }
-            c => panic!("didn't except: {:?}", c),
-        }
-
-        let Some(pid) = assigned_pid else {
-            return Ok(())
         };
 
-        let mut buf = Vec::with_capacity(16);
+        let result = play(&mut stream, metrics, &session, pid, &mut rx).await;
+        session.leave(pid);
 
-        let mut cmds = Vec::new();
-        loop {
-            {
-                let Some(session_mutex) = sessions.get(code).await else {break;};
-                let mut session = session_mutex.lock().unwrap();
-                if session.game.tick(CommandQueue::new(&mut cmds)) {
-                    continue;
-                }
-                for cmd in cmds.drain(..) {
-                    session.send_command(cmd).unwrap();
-                }
+        result
+    }))
+}
+
+async fn play(stream: &mut DuplexStream, metrics: &Metrics, session: &SessionHandle, pid: PlayerId, rx: &mut UnboundedReceiver<Command>) -> rocket_ws::result::Result<()> {
+    let mut buf = Vec::with_capacity(16);
+
+    loop {
+        select! {
+            _ = sleep(Duration::from_secs(5)) => {
+                stream.send(Message::Ping(vec![75, 31, 21, 123, 51, 32])).await?;
             }
-            select! {
-                _ = sleep(Duration::from_secs(5)) => {
-                    stream.send(Message::Ping(vec![75, 31, 21, 123, 51, 32])).await?;
+            n = rx.recv_many(&mut buf, 16) => {
+                if n == 0 {
+                    break;
                 }
-                n = rx.recv_many(&mut buf, 16) => {
-                    if n == 0 {
-                        break;
-                    }
-                    for cmd in buf.drain(..) {
-                        stream.feed(cmd.into_message()).await?;
+                for cmd in buf.drain(..) {
+                    if let Command::Kick(_, reason) = cmd {
+                        close(stream, metrics, Some(CloseFrame {
+                            code: CloseCode::Policy,
+                            reason: Cow::Owned(reason),
+                        })).await?;
+                        return Ok(());
                     }
-                    stream.flush().await?;
+                    metrics.sent(&cmd);
+                    stream.feed(cmd.into_message()).await?;
                 }
-                cmd = handle(&mut stream) => {
-                    let cmd = cmd?;
-                    if let Command::Nop = cmd {
-                        continue;
-                    }
-
-                    let Some(session_mutex) = sessions.get(code).await else {break;};
-                    let mut session = session_mutex.lock().unwrap();
-
-                    match cmd {
-                        Command::Chat(msg) => {
-                            if !msg.is_empty() {
-                                session.send_command(Command::ChatMsg(pid, msg)).unwrap();
-                            }
-                        }
-                        cmd => session.game.handle(pid, cmd, CommandQueue::new(&mut cmds)),
-                    }
+                stream.flush().await?;
+            }
+            cmd = handle(stream, metrics) => {
+                let cmd = cmd?;
+                let cmd = match cmd {
+                    Command::Nop => continue,
+                    // The session only ever keeps a hash of its password
+                    Command::Password(Some(password)) => Command::Password(Some(accounts::hash_password(password).await)),
+                    cmd => cmd,
+                };
+                if !session.send(pid, cmd) {
+                    break;
                 }
             }
         }
+    }
 
-        Ok(())
-    }))
+    Ok(())
 }
 
+/// Sends a command outside of the session, counting it like the ones [`play`] sends
+async fn send(stream: &mut DuplexStream, metrics: &Metrics, cmd: Command) -> rocket_ws::result::Result<()> {
+    metrics.sent(&cmd);
+    stream.send(cmd.into_message()).await
+}
+
+/// Closes the connection from this end, counted by its close code
+async fn close(stream: &mut DuplexStream, metrics: &Metrics, frame: Option<CloseFrame<'static>>) -> rocket_ws::result::Result<()> {
+    metrics.closed(Closer::Server, frame.as_ref().map_or(NO_STATUS, |f| f.code.into()));
+    stream.send(Message::Close(frame)).await
+}
 
-async fn handle(stream: &mut DuplexStream) -> rocket_ws::result::Result<Command> {
+async fn handle(stream: &mut DuplexStream, metrics: &Metrics) -> rocket_ws::result::Result<Command> {
     let Some(message) = stream.next().await else {
-        // TODO: probably close the stream
-        return Ok(Command::Nop)
+        return Err(rocket_ws::result::Error::ConnectionClosed)
     };
     let message = message?;
 
     match message {
-        Message::Close(_) => {
+        Message::Close(frame) => {
+            metrics.closed(Closer::Client, frame.map_or(NO_STATUS, |f| f.code.into()));
             stream.send(Message::Close(None)).await?;
 
             return Err(rocket_ws::result::Error::ConnectionClosed)
@@ -442,7 +555,12 @@ async fn handle(stream: &mut DuplexStream) -> rocket_ws::result::Result<Command>
         Message::Pong(_) => (),
         Message::Ping(vec) => stream.send(Message::Pong(vec)).await?,
         Message::Text(msg) => {
-            return Ok(msg.parse().map_err(|()| rocket_ws::result::Error::Utf8)?);
+            let Ok(cmd) = msg.parse::<Command>() else {
+                metrics.parse_failure();
+                return Err(rocket_ws::result::Error::Utf8);
+            };
+            metrics.received(&cmd);
+            return Ok(cmd);
         }
         message => eprintln!("Got unexpected {:?}", message),
     }
@@ -451,44 +569,219 @@ async fn handle(stream: &mut DuplexStream) -> rocket_ws::result::Result<Command>
  
 #[derive(Clone)]
 pub struct SessionStore {
-    sessions: Arc<RwLock<HashMap<u16, Mutex<Session>>>>,
+    sessions: Arc<RwLock<HashMap<u16, SessionHandle>>>,
+    chat_filter: Arc<ChatFilter>,
+    /// Where sessions are saved between rounds, if anywhere
+    storage: Option<Storage>,
+    /// What every new game is seeded with, if the server was told to deal the same cards every time
+    deck_seed: Option<u64>,
+    pub accounts: Accounts,
+    pub lobby: Lobby,
+    pub metrics: Metrics,
+    matchmaker: Matchmaker,
 }
 
 impl SessionStore {
-    pub fn new() -> Self {
+    pub fn new(chat_filter: ChatFilter, storage: Option<Storage>, deck_seed: Option<u64>) -> Self {
         SessionStore {
             sessions: Arc::new(RwLock::new(HashMap::new())),
+            chat_filter: Arc::new(chat_filter),
+            accounts: Accounts::new(storage.clone()),
+            lobby: Lobby::new(),
+            metrics: Metrics::default(),
+            matchmaker: Matchmaker::default(),
+            storage,
+            deck_seed,
+        }
+    }
+    /// A new game for a session to be opened, seeded if the server was configured to
+    pub fn new_game(&self, game: GameId) -> Box<dyn DynGame> {
+        let mut new_game = game.new_game();
+        if let Some(seed) = self.deck_seed {
+            new_game.seed(seed);
+        }
+        new_game
+    }
+    /// Picks up the sessions and accounts that were saved when the server last stopped
+    pub async fn restore(&self) {
+        self.accounts.restore().await;
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let snapshots = match storage.load_all::<Snapshot>(SNAPSHOTS).await {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                eprintln!("Couldn't restore sessions: {e}");
+                return;
+            }
+        };
+        for (key, snapshot) in snapshots {
+            let Ok(code) = u16::from_str_radix(&key, 16) else {
+                continue;
+            };
+            match Session::restore(snapshot, self.chat_filter.clone()) {
+                Ok(session) => session.resume(code, self).await,
+                Err(e) => eprintln!("Couldn't restore session {key}: {e}"),
+            }
         }
     }
-    pub async fn add(&self, code: u16, session: Session) {
+    /// Stores the session under a new unused code
+    pub async fn add(&self, session: SessionHandle) -> u16 {
         let mut sessions = self.sessions.write().await;
 
-        let mut deads = Vec::new();
-        for (&code, session) in sessions.iter() {
-            match session.lock() {
-                Ok(lock) => if lock.is_empty() {
-                    deads.push(code);
+        sessions.retain(|_, session| !session.is_closed());
+
+        let code = loop {
+            let code = gen_game_code();
+
+            if !sessions.contains_key(&code) {
+                break code;
+            }
+        };
+        sessions.insert(code, session);
+        code
+    }
+    /// Stores the session under a code it already had
+    pub async fn insert(&self, code: u16, session: SessionHandle) {
+        self.sessions.write().await.insert(code, session);
+    }
+    /// Removes the session with this code if its task has ended
+    pub async fn remove_closed(&self, code: u16) {
+        let mut sessions = self.sessions.write().await;
+        if sessions.get(&code).is_some_and(SessionHandle::is_closed) {
+            sessions.remove(&code);
+            self.lobby.update(code, None);
+            if let Some(storage) = &self.storage {
+                if let Err(e) = storage.remove(SNAPSHOTS, &format!("{code:04X}")).await {
+                    eprintln!("Couldn't remove saved session {code:04X}: {e}");
                 }
-                _ => deads.push(code),
             }
         }
-        for dead in deads {
-            sessions.remove(&dead);
+    }
+    pub async fn get(&self, code: u16) -> Option<SessionHandle> {
+        self.sessions.read().await.get(&code).cloned()
+    }
+    /// Every running session as it's listed for admins, by code
+    pub async fn summaries(&self) -> Vec<SessionSummary> {
+        let sessions: Vec<SessionHandle> = self.sessions.read().await.values().cloned().collect();
+        let mut summaries = Vec::with_capacity(sessions.len());
+        for session in sessions {
+            if let Some(summary) = session.summary().await {
+                summaries.push(summary);
+            }
         }
-
-        sessions.insert(code, Mutex::new(session));
+        summaries.sort_by(|a, b| a.code.cmp(&b.code));
+        summaries
+    }
+    /// Shows every running session a notice from the server, returns how many got it
+    pub async fn announce(&self, msg: &str) -> usize {
+        self.sessions.read().await.values().filter(|session| session.announce(msg.to_owned())).count()
     }
-    pub async fn has(&self, code: u16) -> bool {
-        let m = self.sessions.read().await;
-        m.contains_key(&code)
+    /// Counts results in the accounts they were played from
+    async fn record(&self, results: Vec<(String, String, RoundResult)>) {
+        for (token, name, result) in results {
+            self.accounts.record(&token, &name, &result).await;
+        }
+    }
+    /// Every account that has played, best net result first
+    pub async fn leaderboard(&self) -> Vec<Standing> {
+        let mut standings = self.accounts.standings().await;
+        standings.sort_by(|a, b| b.stats.tally.net.cmp(&a.stats.tally.net).then_with(|| a.name.cmp(&b.name)));
+        standings
+    }
+    pub async fn player_stats(&self, name: &str) -> Option<PlayerStats> {
+        self.accounts.stats(name).await
     }
-    pub async fn get(&self, code: u16) -> Option<RwLockReadGuard<'_, Mutex<Session>>> {
-        let m = self.sessions.read().await;
-        if let Some(session) = m.get(&code) {
-            if !session.is_poisoned() {
-                return Some(RwLockReadGuard::map(m, move |m| m.get(&code).unwrap()));
+    /// A finished round from a running session, or from storage once the session is gone
+    pub async fn history(&self, code: u16, round: u32) -> Option<Round> {
+        let live = match self.get(code).await {
+            Some(session) => session.history(round).await,
+            None => None,
+        };
+        if live.is_some() {
+            return live;
+        }
+        let storage = self.storage.as_ref()?;
+        match storage.load(HISTORY, &format!("{code:04X}-{round}")).await {
+            Ok(round) => round,
+            Err(e) => {
+                eprintln!("Couldn't load round {round} of {code:04X}: {e}");
+                None
             }
         }
-        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!("HOST BLACKJACK Alice".parse(), Ok(Command::Host(GameId::Blackjack, Some("Alice".to_owned()))));
+        assert_eq!("JOIN 1A2B Bob hunter2".parse(), Ok(Command::Join(0x1A2B, Some("Bob".to_owned()), Some("hunter2".to_owned()))));
+        assert_eq!("SPECTATE ff".parse(), Ok(Command::Spectate(0xFF, None, None)));
+        assert_eq!(
+            "QUEUE BLACKJACK dealer=S17 Carol".parse(),
+            Ok(Command::Queue(GameId::Blackjack, vec![("dealer".to_owned(), "S17".to_owned())], Some("Carol".to_owned()))),
+        );
+        assert_eq!("CHAT hello  there".parse(), Ok(Command::Chat("hello  there".to_owned())));
+        assert_eq!("BLACKJACK BET 100".parse(), Ok(Command::Game(GameId::Blackjack, "BET 100".to_owned())));
+        assert_eq!("TIMER".parse(), Ok(Command::Timer(None)));
+        assert_eq!("TIMER 0 30".parse(), Ok(Command::Timer(Some((PlayerId::HOST, 30)))));
+        assert_eq!("BOT".parse(), Ok(Command::Bot(Difficulty::Normal)));
+        assert_eq!("PASSWORD".parse(), Ok(Command::Password(None)));
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        for bad in ["", "HOST", "HOST POKER", "JOIN", "JOIN XYZ", "TIMER 0", "KICK one", "BOT IMPOSSIBLE", "ROUND_OVER", "NOTACOMMAND x"] {
+            assert_eq!(bad.parse::<Command>(), Err(()), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn commands_read_back_as_written() {
+        let lines = [
+            "HOST CHATROOM",
+            "HOST BLACKJACK Alice",
+            "JOIN 1A2B Bob hunter2",
+            "SPECTATE FF Sam",
+            "QUEUE BLACKJACK dealer=S17 Carol",
+            "HOST_OK BEEF",
+            "JOIN_OK BEEF Bob",
+            "SPECTATE_OK BEEF Sam",
+            "START",
+            "SET dealer S17",
+            "FINISHED",
+            "ROUND_OVER 3",
+            "SCORES 0 1 1 0 0 100 1 2 0 1 1 -50",
+            "BLACKJACK PLAYERDRAW 0 ♦K",
+            "NAME Dave",
+            "PLAYERS 0 Carol 1 Bob",
+            "WATCHERS 2 Sam",
+            "SPECTATORS 5 CHAT",
+            "SPECTATORS 0",
+            "ERROR Game full",
+            "HOST_IS 1",
+            "KICK 1 too loud",
+            "MUTE 2",
+            "UNMUTE 2",
+            "LOCK",
+            "UNLOCK",
+            "PUBLIC",
+            "PRIVATE",
+            "BOT HARD",
+            "PASSWORD hunter2",
+            "TURNTIME 30",
+            "TIMER 0 30",
+            "TIMER",
+            "CHAT_MSG 1 hello there",
+            "CHAT hello there",
+        ];
+        for line in lines {
+            let cmd: Command = line.parse().unwrap_or_else(|()| panic!("{line:?} didn't parse"));
+            assert_eq!(cmd.to_string(), line);
+        }
     }
 }
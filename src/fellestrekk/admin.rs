@@ -0,0 +1,145 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::Json;
+use rocket::{Request, State};
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::games::GameId;
+
+use super::stats::Tally;
+use super::{PlayerId, SessionStore, MAX_CLOSE_REASON};
+
+/// The token admins authenticate with, from `admin_token` in `Rocket.toml`
+///
+/// Without one the admin routes answer as if they didn't exist.
+#[derive(Debug, Clone, Default)]
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    pub fn new(token: Option<String>) -> Self {
+        AdminToken(token.filter(|t| !t.is_empty()))
+    }
+    fn accepts(&self, given: &str) -> bool {
+        let Some(token) = &self.0 else {
+            return false;
+        };
+        // Compared in full every time, so the time taken doesn't give away how much of a guess was right
+        token.len() == given.len() && token.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+/// A request carrying the admin token as `Authorization: Bearer <token>`
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(token) = req.rocket().state::<AdminToken>().filter(|t| t.0.is_some()) else {
+            return Outcome::Error((Status::NotFound, ()));
+        };
+        let given = req.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Bearer "));
+        match given {
+            Some(given) if token.accepts(given) => Outcome::Success(Admin),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Someone at the table, as admins see them
+#[derive(Debug, Clone, Serialize)]
+pub struct Seat {
+    pub id: PlayerId,
+    pub name: String,
+    pub bot: bool,
+    /// Whether their connection is still open, seats of a restored session wait for theirs
+    pub connected: bool,
+}
+
+/// A running session as it's listed for admins
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub code: String,
+    pub game: GameId,
+    pub host: PlayerId,
+    pub players: Vec<Seat>,
+    pub spectators: Vec<Seat>,
+    pub public: bool,
+    pub locked: bool,
+    /// Seconds since the session was opened, or restored after a restart
+    pub age: u64,
+    /// Seconds since anyone at the table last did anything
+    pub idle: u64,
+}
+
+/// Everything about a session an admin can inspect
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionDetails {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    /// The game's settings by key
+    pub rules: BTreeMap<String, String>,
+    pub turn: Option<PlayerId>,
+    pub next_round: u32,
+    pub scores: BTreeMap<u32, Tally>,
+    /// The chat lines and notices replayed to anyone joining
+    pub chat: Vec<String>,
+    /// The game as it would be saved
+    pub state: Value,
+}
+
+#[derive(Deserialize)]
+pub struct Announcement {
+    message: String,
+}
+
+#[derive(Deserialize)]
+pub struct CloseReason {
+    #[serde(default)]
+    reason: String,
+}
+
+fn parse_code(code: &str) -> Option<u16> {
+    u16::from_str_radix(code, 16).ok()
+}
+
+#[get("/admin/sessions")]
+pub async fn admin_sessions(_admin: Admin, sessions: &State<SessionStore>) -> Json<Vec<SessionSummary>> {
+    Json(sessions.summaries().await)
+}
+
+#[get("/admin/sessions/<code>")]
+pub async fn admin_session(_admin: Admin, code: &str, sessions: &State<SessionStore>) -> Option<Json<SessionDetails>> {
+    let session = sessions.get(parse_code(code)?).await?;
+    session.details().await.map(Json)
+}
+
+/// Shows every session a notice from the server, answers with how many got it
+#[post("/admin/notice", data = "<announcement>")]
+pub async fn admin_notice(_admin: Admin, announcement: Json<Announcement>, sessions: &State<SessionStore>) -> Result<Json<usize>, Status> {
+    let message = announcement.into_inner().message;
+    if message.trim().is_empty() {
+        return Err(Status::BadRequest);
+    }
+    Ok(Json(sessions.announce(&message).await))
+}
+
+/// Sends everyone in the session away with the reason given and ends it
+#[post("/admin/sessions/<code>/close", data = "<reason>")]
+pub async fn admin_close(_admin: Admin, code: &str, reason: Json<CloseReason>, sessions: &State<SessionStore>) -> Status {
+    let reason = match reason.into_inner().reason {
+        reason if reason.is_empty() => "Closed by an admin".to_owned(),
+        reason if reason.len() > MAX_CLOSE_REASON => return Status::BadRequest,
+        reason => reason,
+    };
+    let Some(code) = parse_code(code) else {
+        return Status::NotFound;
+    };
+    match sessions.get(code).await {
+        Some(session) if session.close(reason) => Status::NoContent,
+        _ => Status::NotFound,
+    }
+}
@@ -0,0 +1,70 @@
+use rocket::tokio::sync::mpsc::UnboundedReceiver;
+use rocket::tokio::time::sleep;
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::{Command, PlayerId, SessionHandle};
+
+/// How long a bot takes to make up its mind, so the people at the table can follow along
+const THINKING_TIME: Duration = Duration::from_millis(600);
+
+/// How well a bot plays
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl FromStr for Difficulty {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "EASY" => Ok(Difficulty::Easy),
+            "NORMAL" => Ok(Difficulty::Normal),
+            "HARD" => Ok(Difficulty::Hard),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "EASY"),
+            Difficulty::Normal => write!(f, "NORMAL"),
+            Difficulty::Hard => write!(f, "HARD"),
+        }
+    }
+}
+
+/// A computer-controlled player
+///
+/// A bot is seated like anyone else and gets the same commands a person in its seat would,
+/// only without a socket in between. What it answers with is sent back as if it was typed in.
+pub trait Bot: Send + 'static {
+    /// Reacts to something sent to the bot's seat
+    fn receive(&mut self, pid: PlayerId, cmd: &Command) -> Vec<Command>;
+}
+
+/// Plays the bot's seat until the session lets go of it
+pub(super) async fn run(mut bot: Box<dyn Bot>, pid: PlayerId, mut rx: UnboundedReceiver<Command>, session: SessionHandle) {
+    while let Some(cmd) = rx.recv().await {
+        if let Command::Kick(..) = cmd {
+            break;
+        }
+        let actions = bot.receive(pid, &cmd);
+        if actions.is_empty() {
+            continue;
+        }
+        sleep(THINKING_TIME).await;
+        for action in actions {
+            if !session.send(pid, action) {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::PlayerId;
+
+/// Longest chat message (in characters) the server relays
+pub const MAX_CHAT_LEN: usize = 100;
+/// How many chat messages and notices a session remembers for players joining later
+pub const CHAT_HISTORY_LEN: usize = 50;
+/// A player may send at most this many messages within [`CHAT_RATE_WINDOW`]
+pub const CHAT_RATE_MESSAGES: usize = 5;
+pub const CHAT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Lose,
+    Draw,
+}
+
+/// A server-generated chat line, sent as `SYSTEM <kind> <args>`
+///
+/// The client is responsible for translating these.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notice {
+    Joined(String),
+    Left(String),
+    Kicked(String),
+    Result(PlayerId, Outcome),
+    Text(String),
+}
+
+impl FromStr for Notice {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = s.split_once(' ').ok_or(())?;
+        let pid = || rest.parse().map(PlayerId).map_err(|_| ());
+        Ok(match kind {
+            "JOINED" => Notice::Joined(rest.to_owned()),
+            "LEFT" => Notice::Left(rest.to_owned()),
+            "KICKED" => Notice::Kicked(rest.to_owned()),
+            "WIN" => Notice::Result(pid()?, Outcome::Win),
+            "LOSE" => Notice::Result(pid()?, Outcome::Lose),
+            "DRAW" => Notice::Result(pid()?, Outcome::Draw),
+            "TEXT" => Notice::Text(rest.to_owned()),
+            _ => return Err(()),
+        })
+    }
+}
+
+impl Display for Notice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notice::Joined(name) => write!(f, "JOINED {name}"),
+            Notice::Left(name) => write!(f, "LEFT {name}"),
+            Notice::Kicked(name) => write!(f, "KICKED {name}"),
+            Notice::Result(p, Outcome::Win) => write!(f, "WIN {}", p.0),
+            Notice::Result(p, Outcome::Lose) => write!(f, "LOSE {}", p.0),
+            Notice::Result(p, Outcome::Draw) => write!(f, "DRAW {}", p.0),
+            Notice::Text(msg) => write!(f, "TEXT {msg}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChatError {
+    Muted,
+    NotAllowed,
+    TooLong,
+    TooFast,
+}
+
+impl Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatError::Muted => write!(f, "You are muted"),
+            ChatError::NotAllowed => write!(f, "Spectators cannot chat in this game"),
+            ChatError::TooLong => write!(f, "Message too long"),
+            ChatError::TooFast => write!(f, "You are sending messages too fast"),
+        }
+    }
+}
+
+/// Sliding window of when a player last sent messages
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    sent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    /// Records a message being sent now, unless the player has used up the window
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        while self.sent.front().is_some_and(|&t| now.duration_since(t) >= CHAT_RATE_WINDOW) {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= CHAT_RATE_MESSAGES {
+            false
+        } else {
+            self.sent.push_back(now);
+            true
+        }
+    }
+}
+
+/// Words that get starred out of chat messages, configured with `chat_filter` in `Rocket.toml`
+#[derive(Debug, Clone, Default)]
+pub struct ChatFilter {
+    words: Vec<String>,
+}
+
+impl ChatFilter {
+    pub fn new(words: Vec<String>) -> Self {
+        ChatFilter {
+            words: words.into_iter().map(|w| w.to_lowercase()).filter(|w| !w.is_empty()).collect(),
+        }
+    }
+    pub fn apply(&self, msg: &str) -> String {
+        if self.words.is_empty() {
+            return msg.to_owned();
+        }
+        msg.split(' ')
+            .map(|word| {
+                let bare = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+                if self.words.contains(&bare) {
+                    "*".repeat(word.chars().count())
+                } else {
+                    word.to_owned()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
@@ -0,0 +1,154 @@
+//! What happened in each round of a session, kept for hand histories and replays
+//!
+//! A finished round is served as JSON from `/history/<code>/<round>`:
+//!
+//! ```json
+//! {
+//!   "number": 1,
+//!   "game": "Blackjack",
+//!   "players": [[0, "Alice"]],
+//!   "entries": [
+//!     {"ms": 0, "kind": "action", "player": 0, "data": "BET 10"},
+//!     {"ms": 0, "kind": "event", "to": 0, "data": "TAKEMONEY 10"},
+//!     {"ms": 912, "kind": "action", "player": 0, "data": "START"},
+//!     {"ms": 912, "kind": "log", "data": "SEED 8113027461503394816"},
+//!     {"ms": 912, "kind": "event", "to": null, "data": "START"},
+//!     {"ms": 3046, "kind": "notice", "data": "LOSE 0"}
+//!   ]
+//! }
+//! ```
+//!
+//! `ms` counts from the round's first entry. Entries are, in the order they happened:
+//! - `action`: something a player asked of the game, in the game's own format without the namespace
+//! - `event`: something the game told everyone (`to` is `null`) or a single player, private events included
+//! - `notice`: a `SYSTEM` line the game posted in the chat
+//! - `log`: something the game only wrote down for the history, like the seed a shoe was shuffled with
+//!
+//! A round starts with the first entry after the previous round ended
+//! (so Blackjack's bet is part of the round it was placed for) and ends once the game is between rounds again.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::games::{GameId, GameOutput, Recipient};
+
+use super::PlayerId;
+
+/// How many finished rounds a session keeps in memory
+pub const HISTORY_LEN: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Round {
+    pub number: u32,
+    pub game: GameId,
+    /// Who was seated when the round got going
+    pub players: Vec<(PlayerId, String)>,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub ms: u64,
+    #[serde(flatten)]
+    pub kind: EntryKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum EntryKind {
+    Action { player: PlayerId, data: String },
+    Event { to: Option<PlayerId>, data: String },
+    Notice { data: String },
+    Log { data: String },
+}
+
+/// The rounds of a session so far
+#[derive(Debug)]
+pub struct History {
+    finished: VecDeque<Round>,
+    current: Round,
+    started: Option<Instant>,
+    in_play: bool,
+    /// Finished rounds that haven't been written to storage yet
+    unsaved: Vec<Round>,
+}
+
+impl History {
+    pub fn new(game: GameId, next_round: u32) -> Self {
+        History {
+            finished: VecDeque::with_capacity(HISTORY_LEN),
+            current: Round { number: next_round, game, players: Vec::new(), entries: Vec::new() },
+            started: None,
+            in_play: false,
+            unsaved: Vec::new(),
+        }
+    }
+    /// The number the round being played (or about to be) will get
+    pub fn next_round(&self) -> u32 {
+        self.current.number
+    }
+    fn push(&mut self, kind: EntryKind) {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.current.entries.push(Entry {
+            ms: started.elapsed().as_millis() as u64,
+            kind,
+        });
+    }
+    pub fn action(&mut self, player: PlayerId, data: &str) {
+        self.push(EntryKind::Action { player, data: data.to_owned() });
+    }
+    pub fn log(&mut self, data: String) {
+        self.push(EntryKind::Log { data });
+    }
+    pub fn output(&mut self, recipient: Recipient, output: &GameOutput) {
+        let kind = match output {
+            GameOutput::Event(data) => EntryKind::Event {
+                to: match recipient {
+                    Recipient::All => None,
+                    Recipient::Player(pid) => Some(pid),
+                },
+                data: data.clone(),
+            },
+            GameOutput::Notice(notice) => EntryKind::Notice { data: notice.to_string() },
+            GameOutput::Log(data) => EntryKind::Log { data: data.clone() },
+            // Results are counted in the statistics, what led to them is already in the history
+            GameOutput::Result(_) => return,
+            // Nothing came of it
+            GameOutput::Error(_) => return,
+        };
+        self.push(kind);
+    }
+    /// Keeps track of rounds starting and ending, returns the number of the round that just ended
+    pub fn update(&mut self, between_rounds: bool, players: impl FnOnce() -> Vec<(PlayerId, String)>) -> Option<u32> {
+        if !self.in_play && !between_rounds {
+            self.in_play = true;
+            self.current.players = players();
+            None
+        } else if self.in_play && between_rounds {
+            self.in_play = false;
+            self.started = None;
+            let next = Round {
+                number: self.current.number + 1,
+                game: self.current.game,
+                players: Vec::new(),
+                entries: Vec::new(),
+            };
+            let round = std::mem::replace(&mut self.current, next);
+            let number = round.number;
+            if self.finished.len() == HISTORY_LEN {
+                self.finished.pop_front();
+            }
+            self.finished.push_back(round.clone());
+            self.unsaved.push(round);
+            Some(number)
+        } else {
+            None
+        }
+    }
+    pub fn get(&self, number: u32) -> Option<&Round> {
+        self.finished.iter().find(|r| r.number == number)
+    }
+    pub fn take_unsaved(&mut self) -> Vec<Round> {
+        std::mem::take(&mut self.unsaved)
+    }
+}
@@ -0,0 +1,95 @@
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::tokio::select;
+use rocket::tokio::sync::watch;
+use rocket::State;
+use rocket_ws::{WebSocket, Channel, stream::DuplexStream, Message};
+
+use std::collections::BTreeMap;
+
+use crate::games::GameId;
+
+use super::SessionStore;
+
+/// A public session as it's shown in the lobby
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Listing {
+    pub code: String,
+    pub game: GameId,
+    /// The game's settings by key
+    pub rules: BTreeMap<String, String>,
+    pub host: String,
+    pub seats_taken: usize,
+    /// `None` if the game seats anyone who wants to play
+    pub seats_free: Option<usize>,
+    pub spectators: usize,
+    pub spectators_allowed: bool,
+    pub locked: bool,
+    pub password: bool,
+}
+
+/// The public sessions by code, watched by everyone looking at the lobby
+#[derive(Debug, Clone)]
+pub struct Lobby {
+    listings: watch::Sender<BTreeMap<u16, Listing>>,
+}
+
+impl Lobby {
+    pub fn new() -> Self {
+        Lobby { listings: watch::Sender::new(BTreeMap::new()) }
+    }
+    /// Lists the session as it is now, or takes it off the list, only waking up watchers if something changed
+    pub fn update(&self, code: u16, listing: Option<Listing>) {
+        self.listings.send_if_modified(|listings| match listing {
+            Some(listing) if listings.get(&code) != Some(&listing) => {
+                listings.insert(code, listing);
+                true
+            }
+            Some(_) => false,
+            None => listings.remove(&code).is_some(),
+        });
+    }
+    pub fn games(&self) -> Vec<Listing> {
+        self.listings.borrow().values().cloned().collect()
+    }
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sends the lobby's games as a JSON array, again every time they change
+#[get("/lobby/ws")]
+pub fn lobby_ws(ws: WebSocket, session_store: &State<SessionStore>) -> Channel<'static> {
+    let mut listings = session_store.lobby.listings.subscribe();
+
+    ws.channel(move |mut stream: DuplexStream| Box::pin(async move {
+        loop {
+            let games: Vec<Listing> = listings.borrow_and_update().values().cloned().collect();
+            stream.send(Message::Text(serde_json::to_string(&games).unwrap_or_default())).await?;
+            select! {
+                changed = listings.changed() => if changed.is_err() {
+                    break;
+                },
+                message = next_close(&mut stream) => {
+                    message?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }))
+}
+
+/// Waits for the watcher to go away, answering pings in the meantime
+async fn next_close(stream: &mut DuplexStream) -> rocket_ws::result::Result<()> {
+    while let Some(message) = stream.next().await {
+        match message? {
+            Message::Close(_) => break,
+            Message::Ping(vec) => stream.send(Message::Pong(vec)).await?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use rocket::tokio::sync::mpsc::UnboundedSender;
+use rocket::tokio::sync::{oneshot, Mutex};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::games::{DynGame, GameId, SettingError};
+
+use super::{Command, JoinError, PlayerId, Session, SessionHandle, SessionStore};
+
+/// Players asking for the same game with the same settings
+type QueueKey = (GameId, Vec<(String, String)>);
+
+/// A seat found for a queued player
+#[derive(Debug)]
+pub struct Match {
+    pub code: u16,
+    pub pid: PlayerId,
+    pub name: String,
+    pub session: SessionHandle,
+}
+
+/// A connection waiting for a game
+pub struct Waiting {
+    pub tx: UnboundedSender<Command>,
+    pub name: Option<String>,
+    pub account: Option<String>,
+    pub matched: oneshot::Sender<Match>,
+}
+
+/// Finds seats for players who'd rather not share codes, see [`Command::Queue`]
+///
+/// A player is seated at a public table playing the same game with the same settings if one has room.
+/// Otherwise they wait until enough players want the same, then a new public session is opened for them.
+/// For a game that starts with one player, like Blackjack, that means the first player opens a table
+/// and the ones after them are seated at it until it's full.
+/// Whoever a new table can't seat goes back to waiting.
+#[derive(Clone, Default)]
+pub struct Matchmaker {
+    queues: Arc<Mutex<HashMap<QueueKey, Vec<Waiting>>>>,
+}
+
+impl Matchmaker {
+    pub async fn queue(&self, sessions: &SessionStore, game: GameId, mut settings: Vec<(String, String)>, player: Waiting) -> Result<(), SettingError> {
+        settings.sort();
+        // Checked up front, so nobody is left waiting for a game that can't be set up
+        let min_players = new_game(sessions, game, &settings)?.min_players();
+
+        // Held throughout, so two players never both decide to open a table for the same game
+        let mut queues = self.queues.lock().await;
+
+        let Some(player) = seat_at_open_table(sessions, game, &settings, player).await else {
+            return Ok(());
+        };
+
+        let key = (game, settings);
+        let mut waiting = queues.remove(&key).unwrap_or_default();
+        // Those who gave up waiting have closed their end
+        waiting.retain(|w| !w.matched.is_closed());
+        waiting.push(player);
+        // Every table seats at least whoever opens it, so this runs out
+        while waiting.len() >= min_players {
+            waiting = open_table(sessions, new_game(sessions, game, &key.1)?, waiting).await;
+        }
+        if !waiting.is_empty() {
+            queues.insert(key, waiting);
+        }
+        Ok(())
+    }
+}
+
+fn new_game(sessions: &SessionStore, game: GameId, settings: &[(String, String)]) -> Result<Box<dyn DynGame>, SettingError> {
+    let mut new_game = sessions.new_game(game);
+    for (key, value) in settings {
+        new_game.configure(key, value)?;
+    }
+    Ok(new_game)
+}
+
+/// Opens a public table hosted by the first player, gives back the players it had no seat for
+async fn open_table(sessions: &SessionStore, game: Box<dyn DynGame>, waiting: Vec<Waiting>) -> Vec<Waiting> {
+    let mut players = waiting.into_iter();
+    let Some(host) = players.next() else {
+        return Vec::new();
+    };
+
+    let name = host.name.unwrap_or_else(|| "Player1".to_owned());
+    let mut session = Session::new(host.tx, name.clone(), host.account, game, sessions.chat_filter.clone());
+    session.make_public();
+    let (code, session) = session.open(sessions).await;
+    let _ = host.matched.send(Match { code, pid: PlayerId::HOST, name, session: session.clone() });
+
+    let mut left = Vec::new();
+    for player in players {
+        match join(&session, &player).await {
+            Ok((pid, name)) => {
+                let _ = player.matched.send(Match { code, pid, name, session: session.clone() });
+            }
+            Err(_) => left.push(player),
+        }
+    }
+    left
+}
+
+/// Gives the player back if there was no table for them
+async fn seat_at_open_table(sessions: &SessionStore, game: GameId, settings: &[(String, String)], player: Waiting) -> Option<Waiting> {
+    let open = sessions.lobby.games().into_iter().filter(|listing| {
+        listing.game == game
+            && !listing.locked
+            && !listing.password
+            && listing.seats_free != Some(0)
+            && settings.iter().all(|(key, value)| listing.rules.get(key) == Some(value))
+    });
+    for listing in open {
+        let Ok(code) = u16::from_str_radix(&listing.code, 16) else {
+            continue;
+        };
+        let Some(session) = sessions.get(code).await else {
+            continue;
+        };
+        if let Ok((pid, name)) = join(&session, &player).await {
+            let _ = player.matched.send(Match { code, pid, name, session });
+            return None;
+        }
+    }
+    Some(player)
+}
+
+async fn join(session: &SessionHandle, player: &Waiting) -> Result<(PlayerId, String), JoinError> {
+    let joined = session.join(player.tx.clone(), player.name.clone(), player.account.clone(), None, false).await;
+    match joined {
+        // Someone at the table already goes by that name, so the player gets a made up one
+        Err(JoinError::Name(_)) => session.join(player.tx.clone(), None, player.account.clone(), None, false).await,
+        joined => joined,
+    }
+}
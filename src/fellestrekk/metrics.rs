@@ -0,0 +1,152 @@
+use rocket::http::ContentType;
+use rocket::State;
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::games::{GameId, RoundResult};
+
+use super::{Command, SessionStore};
+
+/// Who closed a WebSocket connection
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Closer {
+    Server,
+    Client,
+}
+
+impl Closer {
+    fn as_str(self) -> &'static str {
+        match self {
+            Closer::Server => "server",
+            Closer::Client => "client",
+        }
+    }
+}
+
+/// Counters and gauges about the whole server, served at `/metrics` for Prometheus to scrape
+///
+/// Every connection and session task holds a clone, they all count into the same numbers.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Counters>>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sessions: BTreeMap<GameId, u64>,
+    sockets: u64,
+    received: BTreeMap<&'static str, u64>,
+    sent: BTreeMap<&'static str, u64>,
+    parse_failures: u64,
+    rounds: BTreeMap<GameId, u64>,
+    wagered: BTreeMap<GameId, u64>,
+    paid_out: BTreeMap<GameId, u64>,
+    /// By who closed the connection and the close code, 1005 when there was none
+    closes: BTreeMap<(Closer, u16), u64>,
+}
+
+/// Counts a connection as open until dropped
+pub struct SocketGuard(Metrics);
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        self.0.update(|c| c.sockets -= 1);
+    }
+}
+
+impl Metrics {
+    fn update(&self, f: impl FnOnce(&mut Counters)) {
+        f(&mut self.inner.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+    pub fn session_opened(&self, game: GameId) {
+        self.update(|c| *c.sessions.entry(game).or_default() += 1);
+    }
+    pub fn session_closed(&self, game: GameId) {
+        self.update(|c| *c.sessions.entry(game).or_default() -= 1);
+    }
+    pub fn socket(&self) -> SocketGuard {
+        self.update(|c| c.sockets += 1);
+        SocketGuard(self.clone())
+    }
+    pub fn received(&self, cmd: &Command) {
+        self.update(|c| *c.received.entry(cmd.kind()).or_default() += 1);
+    }
+    pub fn sent(&self, cmd: &Command) {
+        self.update(|c| *c.sent.entry(cmd.kind()).or_default() += 1);
+    }
+    pub fn parse_failure(&self) {
+        self.update(|c| c.parse_failures += 1);
+    }
+    pub fn round_played(&self, game: GameId) {
+        self.update(|c| *c.rounds.entry(game).or_default() += 1);
+    }
+    /// Counts the chips that went over the table with a player's result
+    pub fn result(&self, game: GameId, result: &RoundResult) {
+        self.update(|c| {
+            *c.wagered.entry(game).or_default() += result.wagered;
+            *c.paid_out.entry(game).or_default() += result.paid_out;
+        });
+    }
+    pub fn closed(&self, by: Closer, code: u16) {
+        self.update(|c| *c.closes.entry((by, code)).or_default() += 1);
+    }
+    /// Everything in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let c = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        // Writing to a string can't fail
+        let _ = c.render(&mut out);
+        out
+    }
+}
+
+impl Counters {
+    fn render(&self, out: &mut String) -> fmt::Result {
+        header(out, "fellestrekk_sessions", "gauge", "Sessions running, by game")?;
+        for (game, n) in &self.sessions {
+            writeln!(out, "fellestrekk_sessions{{game=\"{game}\"}} {n}")?;
+        }
+        header(out, "fellestrekk_sockets", "gauge", "WebSocket connections open")?;
+        writeln!(out, "fellestrekk_sockets {}", self.sockets)?;
+        header(out, "fellestrekk_messages_received_total", "counter", "Commands received from clients, by kind")?;
+        for (kind, n) in &self.received {
+            writeln!(out, "fellestrekk_messages_received_total{{command=\"{kind}\"}} {n}")?;
+        }
+        header(out, "fellestrekk_messages_sent_total", "counter", "Commands sent to clients, by kind")?;
+        for (kind, n) in &self.sent {
+            writeln!(out, "fellestrekk_messages_sent_total{{command=\"{kind}\"}} {n}")?;
+        }
+        header(out, "fellestrekk_parse_failures_total", "counter", "Text messages from clients that weren't a command")?;
+        writeln!(out, "fellestrekk_parse_failures_total {}", self.parse_failures)?;
+        header(out, "fellestrekk_rounds_total", "counter", "Rounds played to the end, by game")?;
+        for (game, n) in &self.rounds {
+            writeln!(out, "fellestrekk_rounds_total{{game=\"{game}\"}} {n}")?;
+        }
+        header(out, "fellestrekk_chips_wagered_total", "counter", "Chips bet by players, side bets included, by game")?;
+        for (game, n) in &self.wagered {
+            writeln!(out, "fellestrekk_chips_wagered_total{{game=\"{game}\"}} {n}")?;
+        }
+        header(out, "fellestrekk_chips_paid_out_total", "counter", "Chips paid back to players, stakes included, by game")?;
+        for (game, n) in &self.paid_out {
+            writeln!(out, "fellestrekk_chips_paid_out_total{{game=\"{game}\"}} {n}")?;
+        }
+        header(out, "fellestrekk_ws_closes_total", "counter", "WebSocket connections closed, by who closed them and the close code")?;
+        for ((by, code), n) in &self.closes {
+            writeln!(out, "fellestrekk_ws_closes_total{{by=\"{}\",code=\"{code}\"}} {n}", by.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} {kind}")
+}
+
+#[get("/metrics")]
+pub fn prometheus(sessions: &State<SessionStore>) -> (ContentType, String) {
+    let content_type = ContentType::new("text", "plain").with_params([("version", "0.0.4"), ("charset", "utf-8")]);
+    (content_type, sessions.metrics.render())
+}
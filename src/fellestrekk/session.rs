@@ -0,0 +1,1086 @@
+use rocket::tokio::select;
+use rocket::tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use rocket::tokio::sync::oneshot;
+use rocket::tokio::time::sleep_until;
+use rocket_ws::frame::{CloseFrame, CloseCode};
+
+use std::sync::Arc;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Display};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::accounts;
+use crate::games::{DynGame, GameId, GameOutput, Outbox, Recipient, RoundResult};
+
+use super::admin::{Seat, SessionDetails, SessionSummary};
+use super::bots::{self, Difficulty};
+use super::chat::{ChatError, ChatFilter, Notice, RateLimiter, CHAT_HISTORY_LEN, MAX_CHAT_LEN};
+use super::history::{History, Round};
+use super::lobby::Listing;
+use super::metrics::Metrics;
+use super::stats::Tally;
+use super::timer::TurnTimer;
+use super::{Command, PlayerId, SessionStore, MAX_CLOSE_REASON};
+
+/// Longest display name (in characters) a player may pick
+pub const MAX_NAME_LEN: usize = 16;
+/// How many spectators a session allows until the host says otherwise
+pub const DEFAULT_MAX_SPECTATORS: u32 = 8;
+/// How long a restored session keeps its players' seats for them to come back to
+pub const RECONNECT_TIME: Duration = Duration::from_secs(5 * 60);
+/// What sessions are saved as in the [`Storage`](crate::storage::Storage)
+pub(super) const SNAPSHOTS: &str = "sessions";
+/// What finished rounds are saved as, keyed by session code and round number
+pub(super) const HISTORY: &str = "history";
+
+struct Player {
+    tx: UnboundedSender<Command>,
+    name: String,
+    /// Token of the account the player connected with, their results are counted there
+    account: Option<String>,
+    muted: bool,
+    chat_limiter: RateLimiter,
+    /// Whether the seat is played by a bot, which doesn't keep the session going on its own
+    bot: bool,
+}
+
+impl Player {
+    fn new(tx: UnboundedSender<Command>, name: String, account: Option<String>) -> Self {
+        Player { tx, name, account, muted: false, chat_limiter: RateLimiter::default(), bot: false }
+    }
+}
+
+/// A game and the people at it
+///
+/// Once opened, the session is owned by its own task, see [`Session::open`].
+/// Connections only talk to it through a [`SessionHandle`], so everything happening at the table is handled in order.
+pub struct Session {
+    players: BTreeMap<u32, Player>,
+    /// Read-only connections, these only get public commands and never reach the game
+    spectators: BTreeMap<u32, Player>,
+    next_id: u32,
+    host: PlayerId,
+    /// Whether new players and spectators are turned away
+    locked: bool,
+    /// Whether the session is listed in the lobby
+    public: bool,
+    /// An argon2 hash of what it takes to join, see [`SessionHandle::join`]
+    password_hash: Option<String>,
+    max_spectators: u32,
+    spectator_chat: bool,
+    /// The latest chat messages and notices, replayed to anyone joining
+    chat_history: VecDeque<Command>,
+    chat_filter: Arc<ChatFilter>,
+    timer: TurnTimer,
+    game: Box<dyn DynGame>,
+    /// Whether everyone has been told the game is over
+    finished: bool,
+    /// When a restored session gives up on the seats nobody has come back to
+    abandon_at: Option<Instant>,
+    history: History,
+    /// How each player has done since the session was opened
+    scores: BTreeMap<u32, Tally>,
+    /// Results that haven't been counted in the players' accounts yet, by account token and player name
+    results: Vec<(String, String, RoundResult)>,
+    /// Whether the game has to be told what the players' wallets hold again, after a seat was taken or a round paid out
+    wallets_changed: bool,
+    /// The session's own handle, which its bots play through
+    handle: Option<SessionHandle>,
+    /// When the session was opened, or restored after a restart
+    opened: Instant,
+    /// When anyone at the table last did anything
+    last_active: Instant,
+    /// The server's metrics, counted into once the session is opened
+    metrics: Metrics,
+    /// Whether a round has ended or the seats or settings have changed since the session was last saved
+    unsaved: bool,
+}
+
+/// What's saved of a session between rounds, enough to pick it up again after a restart
+///
+/// Connections can't be saved, so players get their seats back by joining with the account they sat down with.
+/// Seats of players who didn't have one can't be taken back.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    players: Vec<(PlayerId, String)>,
+    /// The account token of each seat that had one
+    #[serde(default)]
+    accounts: Vec<(PlayerId, String)>,
+    /// Seats bots were playing, the game is told they've left when the session is picked up again
+    #[serde(default)]
+    bots: Vec<PlayerId>,
+    next_id: u32,
+    host: PlayerId,
+    locked: bool,
+    #[serde(default)]
+    public: bool,
+    password_hash: Option<String>,
+    max_spectators: u32,
+    spectator_chat: bool,
+    turn_time: u32,
+    /// In the format they're sent in
+    chat_history: Vec<String>,
+    #[serde(default)]
+    next_round: u32,
+    #[serde(default)]
+    scores: Vec<(PlayerId, Tally)>,
+    game: GameId,
+    state: Value,
+}
+
+/// What a connection can ask of its session's task
+enum SessionMsg {
+    Join {
+        tx: UnboundedSender<Command>,
+        name: Option<String>,
+        account: Option<String>,
+        /// The session's password hash, if the password given matched it
+        password_hash: Option<String>,
+        spectate: bool,
+        reply: oneshot::Sender<Result<(PlayerId, String), JoinError>>,
+    },
+    Command(PlayerId, Command),
+    Leave(PlayerId),
+    PasswordHash(oneshot::Sender<Option<String>>),
+    History {
+        round: u32,
+        reply: oneshot::Sender<Option<Round>>,
+    },
+    Summary(oneshot::Sender<SessionSummary>),
+    Details(oneshot::Sender<SessionDetails>),
+    /// A notice from the server's admins
+    Announce(String),
+    /// Sends everyone away with this reason and ends the session
+    Close(String),
+}
+
+/// A connection's way of talking to a running session
+#[derive(Debug, Clone)]
+pub struct SessionHandle {
+    inbox: UnboundedSender<SessionMsg>,
+}
+
+impl SessionHandle {
+    pub async fn join(&self, tx: UnboundedSender<Command>, name: Option<String>, account: Option<String>, password: Option<String>, spectate: bool) -> Result<(PlayerId, String), JoinError> {
+        let password_hash = match password {
+            Some(password) => self.check_password(password).await?,
+            None => None,
+        };
+        let (reply, answer) = oneshot::channel();
+        self.inbox.send(SessionMsg::Join { tx, name, account, password_hash, spectate, reply })
+            .map_err(|_| JoinError::NoSuchGame)?;
+        answer.await.unwrap_or(Err(JoinError::NoSuchGame))
+    }
+    /// The session's password hash if `password` matches it
+    ///
+    /// Hashing is slow on purpose, so the password is checked here rather than holding up everyone at the table.
+    async fn check_password(&self, password: String) -> Result<Option<String>, JoinError> {
+        let (reply, answer) = oneshot::channel();
+        self.inbox.send(SessionMsg::PasswordHash(reply)).map_err(|_| JoinError::NoSuchGame)?;
+        let Some(hash) = answer.await.map_err(|_| JoinError::NoSuchGame)? else {
+            return Ok(None);
+        };
+        Ok(accounts::verify_password(password, hash.clone()).await.then_some(hash))
+    }
+    /// Passes on a command from the player, returns false if the session has ended
+    pub fn send(&self, pid: PlayerId, cmd: Command) -> bool {
+        self.inbox.send(SessionMsg::Command(pid, cmd)).is_ok()
+    }
+    pub fn leave(&self, pid: PlayerId) {
+        let _ = self.inbox.send(SessionMsg::Leave(pid));
+    }
+    /// A round that has been played to the end
+    pub async fn history(&self, round: u32) -> Option<Round> {
+        let (reply, answer) = oneshot::channel();
+        self.inbox.send(SessionMsg::History { round, reply }).ok()?;
+        answer.await.ok().flatten()
+    }
+    /// How the session is listed for admins, `None` if it has ended
+    pub async fn summary(&self) -> Option<SessionSummary> {
+        let (reply, answer) = oneshot::channel();
+        self.inbox.send(SessionMsg::Summary(reply)).ok()?;
+        answer.await.ok()
+    }
+    /// Everything an admin can inspect about the session, `None` if it has ended
+    pub async fn details(&self) -> Option<SessionDetails> {
+        let (reply, answer) = oneshot::channel();
+        self.inbox.send(SessionMsg::Details(reply)).ok()?;
+        answer.await.ok()
+    }
+    /// Shows everyone at the table a notice from the server, returns false if the session has ended
+    pub fn announce(&self, msg: String) -> bool {
+        self.inbox.send(SessionMsg::Announce(msg)).is_ok()
+    }
+    /// Sends everyone away with the reason given and ends the session, returns false if it already had
+    pub fn close(&self, reason: String) -> bool {
+        self.inbox.send(SessionMsg::Close(reason)).is_ok()
+    }
+    /// Whether the session's task has stopped
+    pub fn is_closed(&self) -> bool {
+        self.inbox.is_closed()
+    }
+}
+
+impl fmt::Debug for SessionMsg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionMsg::Join { name, spectate, .. } => write!(f, "Join({name:?}, spectate: {spectate})"),
+            SessionMsg::Command(pid, cmd) => write!(f, "Command({pid:?}, {cmd:?})"),
+            SessionMsg::Leave(pid) => write!(f, "Leave({pid:?})"),
+            SessionMsg::PasswordHash(_) => write!(f, "PasswordHash"),
+            SessionMsg::History { round, .. } => write!(f, "History({round})"),
+            SessionMsg::Summary(_) => write!(f, "Summary"),
+            SessionMsg::Details(_) => write!(f, "Details"),
+            SessionMsg::Announce(msg) => write!(f, "Announce({msg:?})"),
+            SessionMsg::Close(reason) => write!(f, "Close({reason:?})"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NameError {
+    Invalid,
+    Taken,
+}
+
+impl Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Invalid => write!(f, "Invalid name"),
+            NameError::Taken => write!(f, "Name taken"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    NoSuchGame,
+    Full,
+    NoSpectators,
+    Locked,
+    WrongPassword,
+    Name(NameError),
+}
+
+impl JoinError {
+    pub(crate) fn close_frame(self) -> CloseFrame<'static> {
+        let code = match self {
+            JoinError::Full | JoinError::NoSpectators => CloseCode::Again,
+            _ => CloseCode::Policy,
+        };
+        CloseFrame {
+            code,
+            reason: Cow::Owned(self.to_string()),
+        }
+    }
+}
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::NoSuchGame => write!(f, "No such game"),
+            JoinError::Full => write!(f, "Game full"),
+            JoinError::NoSpectators => write!(f, "No room for spectators"),
+            JoinError::Locked => write!(f, "Game locked"),
+            JoinError::WrongPassword => write!(f, "Wrong password"),
+            JoinError::Name(e) => e.fmt(f),
+        }
+    }
+}
+
+pub(crate) fn valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LEN).contains(&name.chars().count())
+        && !name.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+impl Session {
+    #[inline]
+    pub fn new(host: UnboundedSender<Command>, name: String, account: Option<String>, game: Box<dyn DynGame>, chat_filter: Arc<ChatFilter>) -> Self {
+        let mut players = BTreeMap::new();
+        players.insert(PlayerId::HOST.0, Player::new(host, name, account));
+        Session {
+            players,
+            spectators: BTreeMap::new(),
+            next_id: PlayerId::HOST.0 + 1,
+            host: PlayerId::HOST,
+            locked: false,
+            public: false,
+            password_hash: None,
+            max_spectators: DEFAULT_MAX_SPECTATORS,
+            spectator_chat: true,
+            chat_history: VecDeque::with_capacity(CHAT_HISTORY_LEN),
+            chat_filter,
+            timer: TurnTimer::new(),
+            history: History::new(game.id(), 1),
+            game,
+            finished: false,
+            abandon_at: None,
+            scores: BTreeMap::new(),
+            results: Vec::new(),
+            wallets_changed: true,
+            handle: None,
+            opened: Instant::now(),
+            last_active: Instant::now(),
+            metrics: Metrics::default(),
+            unsaved: true,
+        }
+    }
+    /// Lists the session in the lobby from the start
+    pub fn make_public(&mut self) {
+        self.public = true;
+    }
+    /// Sets a saved session up again, with every seat waiting for its player to reconnect
+    ///
+    /// Seats are given back by account, so those of players without one are freed straight away.
+    pub fn restore(snapshot: Snapshot, chat_filter: Arc<ChatFilter>) -> serde_json::Result<Self> {
+        let mut game = snapshot.game.load(snapshot.state)?;
+        for pid in snapshot.bots {
+            game.on_leave(pid, &mut Vec::new());
+        }
+        let mut timer = TurnTimer::new();
+        timer.turn_time = snapshot.turn_time;
+        let mut accounts: BTreeMap<_, _> = snapshot.accounts.into_iter().collect();
+        let mut players = BTreeMap::new();
+        for (pid, name) in snapshot.players {
+            match accounts.remove(&pid) {
+                Some(account) => {
+                    players.insert(pid.0, Player::new(unbounded_channel().0, name, Some(account)));
+                }
+                None => game.on_leave(pid, &mut Vec::new()),
+            }
+        }
+        Ok(Session {
+            players,
+            spectators: BTreeMap::new(),
+            next_id: snapshot.next_id,
+            host: snapshot.host,
+            locked: snapshot.locked,
+            public: snapshot.public,
+            password_hash: snapshot.password_hash,
+            max_spectators: snapshot.max_spectators,
+            spectator_chat: snapshot.spectator_chat,
+            chat_history: snapshot.chat_history.iter().filter_map(|cmd| cmd.parse().ok()).collect(),
+            chat_filter,
+            timer,
+            history: History::new(snapshot.game, snapshot.next_round.max(1)),
+            game,
+            finished: false,
+            abandon_at: Some(Instant::now() + RECONNECT_TIME),
+            scores: snapshot.scores.into_iter().map(|(pid, tally)| (pid.0, tally)).collect(),
+            results: Vec::new(),
+            wallets_changed: true,
+            handle: None,
+            opened: Instant::now(),
+            last_active: Instant::now(),
+            metrics: Metrics::default(),
+            unsaved: false,
+        })
+    }
+    fn snapshot(&self) -> serde_json::Result<Snapshot> {
+        Ok(Snapshot {
+            // Bots can't reconnect, so their seats aren't kept
+            players: self.players.iter().filter(|(_, p)| !p.bot).map(|(&id, p)| (PlayerId(id), p.name.clone())).collect(),
+            accounts: self.players.iter()
+                .filter_map(|(&id, p)| Some((PlayerId(id), p.account.clone()?)))
+                .collect(),
+            bots: self.players.iter().filter(|(_, p)| p.bot).map(|(&id, _)| PlayerId(id)).collect(),
+            next_id: self.next_id,
+            host: self.host,
+            locked: self.locked,
+            public: self.public,
+            password_hash: self.password_hash.clone(),
+            max_spectators: self.max_spectators,
+            spectator_chat: self.spectator_chat,
+            turn_time: self.timer.turn_time,
+            chat_history: self.chat_history.iter().map(|cmd| cmd.to_string()).collect(),
+            next_round: self.history.next_round(),
+            scores: self.scores.iter().map(|(&id, &tally)| (PlayerId(id), tally)).collect(),
+            game: self.game.id(),
+            state: self.game.save()?,
+        })
+    }
+    async fn save(&self, code: u16, sessions: &SessionStore) {
+        let Some(storage) = &sessions.storage else {
+            return;
+        };
+        let saved = match self.snapshot() {
+            Ok(snapshot) => storage.save(SNAPSHOTS, &format!("{code:04X}"), &snapshot).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = saved {
+            eprintln!("Couldn't save session {code:04X}: {e}");
+        }
+    }
+    async fn save_history(&mut self, code: u16, sessions: &SessionStore) {
+        let rounds = self.history.take_unsaved();
+        let Some(storage) = &sessions.storage else {
+            return;
+        };
+        for round in rounds {
+            if let Err(e) = storage.save(HISTORY, &format!("{code:04X}-{}", round.number), &round).await {
+                eprintln!("Couldn't save round {} of {code:04X}: {e}", round.number);
+            }
+        }
+    }
+    /// Gives the session a code in the store and spawns the task owning it
+    ///
+    /// The task runs until every player has left, then takes the session out of the store again.
+    pub async fn open(mut self, sessions: &SessionStore) -> (u16, SessionHandle) {
+        let (inbox, messages) = unbounded_channel();
+        let handle = SessionHandle { inbox };
+        self.handle = Some(handle.clone());
+        self.metrics = sessions.metrics.clone();
+        let code = sessions.add(handle.clone()).await;
+        rocket::tokio::spawn(self.run(code, messages, sessions.clone()));
+        (code, handle)
+    }
+    /// Like [`Session::open`], but under the code the session had before
+    pub async fn resume(mut self, code: u16, sessions: &SessionStore) {
+        let (inbox, messages) = unbounded_channel();
+        let handle = SessionHandle { inbox };
+        self.handle = Some(handle.clone());
+        self.metrics = sessions.metrics.clone();
+        sessions.insert(code, handle).await;
+        rocket::tokio::spawn(self.run(code, messages, sessions.clone()));
+    }
+    async fn run(mut self, code: u16, mut messages: UnboundedReceiver<SessionMsg>, sessions: SessionStore) {
+        self.metrics.session_opened(self.game.id());
+        if let (None, Some(host)) = (self.abandon_at, self.players.get(&PlayerId::HOST.0)) {
+            // The host is caught up like anyone joining later
+            self.greet(&host.tx);
+            let name = host.name.clone();
+            self.send_command(self.roster());
+            self.game_event(|game, out| game.on_join(PlayerId::HOST, out));
+            self.game.set_name(PlayerId::HOST, &name);
+        }
+        sessions.lobby.update(code, self.listing(code));
+        loop {
+            if std::mem::take(&mut self.wallets_changed) {
+                self.update_wallets(&sessions).await;
+            }
+            let wake = self.timer.deadline().into_iter().chain(self.abandon_at).min();
+            let msg = match wake {
+                Some(wake) => select! {
+                    msg = messages.recv() => Some(msg),
+                    _ = sleep_until(wake.into()) => None,
+                },
+                None => Some(messages.recv().await),
+            };
+            match msg {
+                Some(Some(msg)) => self.receive(code, msg),
+                Some(None) => break,
+                None if self.abandon_at.is_some_and(|t| t <= Instant::now()) => self.free_seats(),
+                None => self.expire_turn(),
+            }
+            // A restored session waits for its players until it's abandoned
+            if self.abandon_at.is_none() && self.is_empty() {
+                break;
+            }
+            self.flush_game(Vec::new());
+            self.update_turn();
+            if self.unsaved && self.game.between_rounds() {
+                self.unsaved = false;
+                self.save(code, &sessions).await;
+            }
+            self.save_history(code, &sessions).await;
+            sessions.record(std::mem::take(&mut self.results)).await;
+            sessions.lobby.update(code, self.listing(code));
+        }
+        drop(messages);
+        sessions.remove_closed(code).await;
+        self.metrics.session_closed(self.game.id());
+    }
+    fn receive(&mut self, code: u16, msg: SessionMsg) {
+        if matches!(msg, SessionMsg::Join { .. } | SessionMsg::Command(..) | SessionMsg::Leave(_)) {
+            self.last_active = Instant::now();
+        }
+        match msg {
+            SessionMsg::Join { tx, name, account, password_hash, spectate, reply } => {
+                let joined = if spectate {
+                    self.spectate(tx, name, password_hash.as_deref())
+                } else {
+                    self.join(tx, name, account, password_hash.as_deref())
+                };
+                let _ = reply.send(joined);
+            }
+            SessionMsg::Command(pid, cmd) => self.handle(pid, cmd),
+            SessionMsg::Leave(pid) => self.leave(pid),
+            SessionMsg::PasswordHash(reply) => {
+                let _ = reply.send(self.password_hash.clone());
+            }
+            SessionMsg::History { round, reply } => {
+                let _ = reply.send(self.history.get(round).cloned());
+            }
+            SessionMsg::Summary(reply) => {
+                let _ = reply.send(self.summary(code));
+            }
+            SessionMsg::Details(reply) => {
+                let _ = reply.send(self.details(code));
+            }
+            SessionMsg::Announce(msg) => self.notice(Notice::Text(msg)),
+            SessionMsg::Close(reason) => self.close(reason),
+        }
+    }
+    fn handle(&mut self, pid: PlayerId, cmd: Command) {
+        // Kicked, but their socket hasn't closed yet
+        if self.get(pid).is_none() {
+            return;
+        }
+        let spectator = self.is_spectator(pid);
+
+        match cmd {
+            Command::Chat(msg) => {
+                if let Err(e) = self.chat(pid, msg) {
+                    self.send_to(pid, Command::Error(e.to_string()));
+                }
+            }
+            Command::Name(name) => {
+                if let Err(e) = self.rename(pid, name) {
+                    self.send_to(pid, Command::Error(e.to_string()));
+                }
+            }
+            cmd if cmd.is_host_only() => {
+                if self.is_host(pid) {
+                    self.moderate(cmd);
+                } else {
+                    self.send_to(pid, Command::Error("Only the host can do that".to_owned()));
+                }
+            }
+            Command::Scores(_) => self.send_to(pid, self.scores()),
+            _ if spectator => (),
+            Command::Game(game, _) if game != self.game.id() => {
+                self.send_to(pid, Command::Error(format!("This session is playing {}", self.game.id())));
+            }
+            Command::Game(..) if self.finished => {
+                self.send_to(pid, Command::Error("The game is over".to_owned()));
+            }
+            Command::Game(_, action) => {
+                let mut cmds = Vec::new();
+                if self.game.handle(pid, &action, &mut cmds) {
+                    self.history.action(pid, &action);
+                    self.flush_game(cmds);
+                    self.update_turn();
+                } else {
+                    self.send_to(pid, Command::Error("Unknown command".to_owned()));
+                }
+            }
+            _ => (),
+        }
+    }
+    /// Sends a command to every player and spectator in the session
+    ///
+    /// Players whose connection has gone away are skipped, they get removed once their leave message comes through.
+    fn send_command(&self, cmd: Command) {
+        for player in self.players.values().chain(self.spectators.values()) {
+            let _ = player.tx.send(cmd.clone());
+        }
+    }
+    fn send_to(&self, pid: PlayerId, cmd: Command) {
+        if let Some(player) = self.get(pid) {
+            let _ = player.tx.send(cmd);
+        }
+    }
+    /// Lets the game catch up and sends out everything it has queued
+    fn flush_game(&mut self, cmds: Outbox) {
+        // Sent out before the game gets to tick, as a round can start and finish
+        // in the same go (like a natural in Blackjack) and the history has to see both
+        self.send_outputs(cmds);
+        let mut cmds = Vec::new();
+        while self.game.tick(&mut cmds) {}
+        self.send_outputs(cmds);
+        if !self.finished && self.game.is_finished() {
+            self.finished = true;
+            self.send_command(Command::Finished);
+        }
+    }
+    fn send_outputs(&mut self, cmds: Outbox) {
+        let mut scored = false;
+        for (recipient, output) in cmds {
+            self.history.output(recipient, &output);
+            let cmd = match output {
+                GameOutput::Event(event) => Command::Game(self.game.id(), event),
+                GameOutput::Notice(notice) => Command::System(notice),
+                GameOutput::Log(_) => continue,
+                GameOutput::Error(e) => Command::Error(e),
+                GameOutput::Result(result) => {
+                    if let Recipient::Player(pid) = recipient {
+                        self.record_result(pid, result);
+                        scored = true;
+                    }
+                    continue;
+                }
+            };
+            self.dispatch(recipient, cmd);
+        }
+        if scored {
+            self.send_command(self.scores());
+        }
+        let players = &self.players;
+        let roster = || players.iter().map(|(&id, p)| (PlayerId(id), p.name.clone())).collect();
+        if let Some(round) = self.history.update(self.game.between_rounds(), roster) {
+            self.metrics.round_played(self.game.id());
+            self.unsaved = true;
+            self.send_command(Command::RoundOver(round));
+        }
+    }
+    /// Calls one of the game's hooks and sends out what came of it
+    fn game_event(&mut self, event: impl FnOnce(&mut dyn DynGame, &mut Outbox)) {
+        let mut cmds = Vec::new();
+        event(&mut *self.game, &mut cmds);
+        self.flush_game(cmds);
+    }
+    /// Restarts the turn timer once the turn has passed on, not on every move the player makes
+    fn update_turn(&mut self) {
+        if self.game.turn() != self.timer.turn() {
+            self.restart_timer();
+        }
+    }
+    fn restart_timer(&mut self) {
+        let turn = self.game.turn();
+        let seconds = self.timer.start(turn);
+        self.send_command(Command::Timer(turn.zip(seconds)));
+    }
+    /// Makes the default move for a player that ran out of time
+    fn expire_turn(&mut self) {
+        if let Some(pid) = self.timer.expire() {
+            self.history.log(format!("TIMEOUT {pid}"));
+            self.game_event(|game, out| game.on_timer(pid, out));
+            self.restart_timer();
+        }
+    }
+    fn set_turn_time(&mut self, seconds: u32) {
+        self.timer.turn_time = seconds;
+        self.send_command(Command::TurnTime(seconds));
+        self.restart_timer();
+    }
+    fn dispatch(&mut self, recipient: Recipient, cmd: Command) {
+        match recipient {
+            Recipient::All => self.broadcast_chat(cmd),
+            Recipient::Player(pid) => self.send_to(pid, cmd),
+        }
+    }
+    /// Like [`Session::send_command`], but chat lines are also kept in the history
+    fn broadcast_chat(&mut self, cmd: Command) {
+        if let Command::ChatMsg(..) | Command::System(_) = cmd {
+            if self.chat_history.len() == CHAT_HISTORY_LEN {
+                self.chat_history.pop_front();
+            }
+            self.chat_history.push_back(cmd.clone());
+        }
+        self.send_command(cmd);
+    }
+    fn notice(&mut self, notice: Notice) {
+        self.broadcast_chat(Command::System(notice));
+    }
+    fn chat(&mut self, pid: PlayerId, msg: String) -> Result<(), ChatError> {
+        if msg.is_empty() {
+            return Ok(());
+        }
+        if self.is_spectator(pid) && !self.spectator_chat {
+            return Err(ChatError::NotAllowed);
+        }
+        if msg.chars().count() > MAX_CHAT_LEN {
+            return Err(ChatError::TooLong);
+        }
+        let Some(player) = self.get_mut(pid) else {
+            return Ok(());
+        };
+        if player.muted {
+            return Err(ChatError::Muted);
+        }
+        if !player.chat_limiter.allow() {
+            return Err(ChatError::TooFast);
+        }
+        let msg = self.chat_filter.apply(&msg);
+        self.broadcast_chat(Command::ChatMsg(pid, msg));
+        Ok(())
+    }
+    /// Whether every player but the bots has left (which should end the session)
+    fn is_empty(&self) -> bool {
+        self.players.values().filter(|p| !p.bot).all(|p| p.tx.is_closed())
+    }
+    fn check_name(&self, name: &str, except: Option<PlayerId>) -> Result<(), NameError> {
+        if !valid_name(name) {
+            return Err(NameError::Invalid);
+        }
+        let taken = self.players.iter().chain(&self.spectators)
+            .filter(|&(&id, _)| Some(PlayerId(id)) != except)
+            .any(|(_, p)| p.name.to_lowercase() == name.to_lowercase());
+        if taken {
+            Err(NameError::Taken)
+        } else {
+            Ok(())
+        }
+    }
+    fn default_name(&self) -> String {
+        (1..).map(|n| format!("Player{n}"))
+            .find(|name| self.check_name(name, None).is_ok())
+            .unwrap()
+    }
+    fn pick_name(&self, name: Option<String>) -> Result<String, JoinError> {
+        match name {
+            Some(name) => {
+                self.check_name(&name, None).map_err(JoinError::Name)?;
+                Ok(name)
+            }
+            None => Ok(self.default_name()),
+        }
+    }
+    fn new_id(&mut self) -> PlayerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        PlayerId(id)
+    }
+    fn check_entry(&self, password_hash: Option<&str>) -> Result<(), JoinError> {
+        if self.locked {
+            return Err(JoinError::Locked);
+        }
+        self.check_password(password_hash)
+    }
+    fn check_password(&self, password_hash: Option<&str>) -> Result<(), JoinError> {
+        match &self.password_hash {
+            Some(hash) if Some(&**hash) != password_hash => Err(JoinError::WrongPassword),
+            _ => Ok(()),
+        }
+    }
+    fn join(&mut self, tx: UnboundedSender<Command>, name: Option<String>, account: Option<String>, password_hash: Option<&str>) -> Result<(PlayerId, String), JoinError> {
+        if let Some(pid) = self.empty_seat(account.as_deref()) {
+            // Their seat was kept for them, even if the game has been locked since
+            self.check_password(password_hash)?;
+            return Ok(self.reclaim(pid, tx));
+        }
+        self.check_entry(password_hash)?;
+        if self.players.len() >= self.game.max_players() {
+            return Err(JoinError::Full);
+        }
+        let name = self.pick_name(name)?;
+        let pid = self.seat(Player::new(tx, name.clone(), account));
+        Ok((pid, name))
+    }
+    fn seat(&mut self, player: Player) -> PlayerId {
+        let pid = self.new_id();
+        let name = player.name.clone();
+        self.greet(&player.tx);
+        self.players.insert(pid.0, player);
+        self.unsaved = true;
+        self.wallets_changed = true;
+        self.send_command(self.roster());
+        self.notice(Notice::Joined(name.clone()));
+        self.game_event(|game, out| game.on_join(pid, out));
+        self.game.set_name(pid, &name);
+        pid
+    }
+    /// Seats a bot, played by a task of its own that talks to the session like a connection would
+    fn add_bot(&mut self, difficulty: Difficulty) -> Result<(), &'static str> {
+        let bot = self.game.id().bot(difficulty).ok_or("There are no bots for this game")?;
+        if self.players.len() >= self.game.max_players() {
+            return Err("Game full");
+        }
+        let Some(session) = self.handle.clone() else {
+            return Err("The session isn't running");
+        };
+        let name = (1..).map(|n| format!("Bot{n}"))
+            .find(|name| self.check_name(name, None).is_ok())
+            .unwrap();
+        let (tx, rx) = unbounded_channel();
+        let mut player = Player::new(tx, name, None);
+        player.bot = true;
+        let pid = self.seat(player);
+        rocket::tokio::spawn(bots::run(bot, pid, rx, session));
+        Ok(())
+    }
+    /// A seat kept from before a restart for the player with this account
+    fn empty_seat(&self, account: Option<&str>) -> Option<PlayerId> {
+        let account = account?;
+        self.players.iter()
+            .find(|(_, p)| p.tx.is_closed() && p.account.as_deref() == Some(account))
+            .map(|(&id, _)| PlayerId(id))
+    }
+    fn reclaim(&mut self, pid: PlayerId, tx: UnboundedSender<Command>) -> (PlayerId, String) {
+        self.greet(&tx);
+        let player = self.players.get_mut(&pid.0).unwrap();
+        player.tx = tx;
+        self.wallets_changed = true;
+        let name = player.name.clone();
+        self.send_command(self.roster());
+        // Someone at the table runs it until the host is back
+        if self.players.get(&self.host.0).is_none_or(|host| host.tx.is_closed()) {
+            self.host = pid;
+            self.send_command(Command::HostIs(pid));
+        }
+        self.notice(Notice::Joined(name.clone()));
+        self.game_event(|game, out| game.on_join(pid, out));
+        self.game.set_name(pid, &name);
+        (pid, name)
+    }
+    /// Gives up on the seats kept from before a restart that nobody has come back to
+    fn free_seats(&mut self) {
+        self.abandon_at = None;
+        let empty: Vec<PlayerId> = self.players.iter()
+            .filter(|(_, p)| !p.bot && p.tx.is_closed())
+            .map(|(&id, _)| PlayerId(id))
+            .collect();
+        for pid in empty {
+            self.leave(pid);
+        }
+    }
+    fn spectate(&mut self, tx: UnboundedSender<Command>, name: Option<String>, password_hash: Option<&str>) -> Result<(PlayerId, String), JoinError> {
+        self.check_entry(password_hash)?;
+        if self.spectators.len() >= self.max_spectators as usize {
+            return Err(JoinError::NoSpectators);
+        }
+        let name = self.pick_name(name)?;
+        let pid = self.new_id();
+        self.greet(&tx);
+        self.spectators.insert(pid.0, Player::new(tx, name.clone(), None));
+        self.send_command(self.spectator_roster());
+        self.notice(Notice::Joined(name.clone()));
+        Ok((pid, name))
+    }
+    fn record_result(&mut self, pid: PlayerId, result: RoundResult) {
+        let Some(player) = self.players.get(&pid.0) else {
+            return;
+        };
+        self.metrics.result(self.game.id(), &result);
+        self.scores.entry(pid.0).or_default().add(&result);
+        // Players without an account only show up on the session's own scoreboard
+        if let Some(account) = &player.account {
+            self.results.push((account.clone(), player.name.clone(), result));
+            self.wallets_changed = true;
+        }
+    }
+    /// Tells the game what the wallet of each player with an account holds, so nobody bets chips they don't have
+    async fn update_wallets(&mut self, sessions: &SessionStore) {
+        for (&id, player) in &self.players {
+            let Some(token) = &player.account else {
+                continue;
+            };
+            let wallet = sessions.accounts.wallet(token).await;
+            self.game.set_bankroll(PlayerId(id), wallet.map(|chips| u64::try_from(chips).unwrap_or(0)));
+        }
+    }
+    /// The scoreboard of everyone seated
+    fn scores(&self) -> Command {
+        Command::Scores(self.players.keys()
+            .map(|&id| (PlayerId(id), self.scores.get(&id).copied().unwrap_or_default()))
+            .collect())
+    }
+    /// Catches a new connection up on who the host is, how the game is set up and what has been said
+    fn greet(&self, tx: &UnboundedSender<Command>) {
+        let _ = tx.send(Command::HostIs(self.host));
+        let _ = tx.send(self.scores());
+        for setting in self.game.settings() {
+            let _ = tx.send(Command::Setting(setting));
+        }
+        if self.finished {
+            let _ = tx.send(Command::Finished);
+        }
+        for cmd in &self.chat_history {
+            let _ = tx.send(cmd.clone());
+        }
+    }
+    fn is_spectator(&self, pid: PlayerId) -> bool {
+        self.spectators.contains_key(&pid.0)
+    }
+    fn leave(&mut self, pid: PlayerId) {
+        if let Some(player) = self.remove(pid) {
+            self.notice(Notice::Left(player.name));
+        }
+    }
+    fn remove(&mut self, pid: PlayerId) -> Option<Player> {
+        if let Some(player) = self.players.remove(&pid.0) {
+            self.unsaved = true;
+            self.send_command(self.roster());
+            if self.host == pid {
+                // A bot or a seat still waiting for its player only gets to host if it's all that's left
+                let next = self.players.iter().find(|(_, p)| !p.bot && !p.tx.is_closed())
+                    .or_else(|| self.players.iter().next());
+                if let Some((&next, _)) = next {
+                    self.host = PlayerId(next);
+                    self.send_command(Command::HostIs(self.host));
+                }
+            }
+            self.game_event(|game, out| game.on_leave(pid, out));
+            Some(player)
+        } else if let Some(spectator) = self.spectators.remove(&pid.0) {
+            self.send_command(self.spectator_roster());
+            Some(spectator)
+        } else {
+            None
+        }
+    }
+    fn is_host(&self, pid: PlayerId) -> bool {
+        self.host == pid
+    }
+    fn get(&self, pid: PlayerId) -> Option<&Player> {
+        self.players.get(&pid.0).or_else(|| self.spectators.get(&pid.0))
+    }
+    fn get_mut(&mut self, pid: PlayerId) -> Option<&mut Player> {
+        match self.players.get_mut(&pid.0) {
+            Some(player) => Some(player),
+            None => self.spectators.get_mut(&pid.0),
+        }
+    }
+    /// Applies one of the host-only commands, see [`Command::is_host_only`]
+    fn moderate(&mut self, cmd: Command) {
+        // Everything else changes a setting or a seat
+        self.unsaved |= !matches!(cmd, Command::Start);
+        match cmd {
+            Command::Start => {
+                if let Err(e) = self.start() {
+                    self.send_to(self.host, Command::Error(e.to_owned()));
+                }
+            }
+            Command::Bot(difficulty) => {
+                if let Err(e) = self.add_bot(difficulty) {
+                    self.send_to(self.host, Command::Error(e.to_owned()));
+                }
+            }
+            Command::Set(key, value) => match self.game.configure(&key, &value) {
+                Ok(()) => self.send_settings(),
+                Err(e) => self.send_to(self.host, Command::Error(e.to_string())),
+            },
+            Command::Kick(_, reason) if reason.len() > MAX_CLOSE_REASON => {
+                self.send_to(self.host, Command::Error("Kick reason too long".to_owned()));
+            }
+            Command::Kick(target, reason) => self.kick(target, reason),
+            Command::Mute(target, muted) => {
+                if let Some(player) = self.get_mut(target) {
+                    player.muted = muted;
+                    self.send_command(Command::Mute(target, muted));
+                }
+            }
+            Command::Lock(locked) => {
+                self.locked = locked;
+                self.send_command(Command::Lock(locked));
+            }
+            Command::Public(public) => {
+                self.public = public;
+                self.send_command(Command::Public(public));
+            }
+            // Hashed by the connection it came in on
+            Command::Password(password_hash) => self.password_hash = password_hash,
+            Command::SpectatorSettings { max, chat } => self.set_spectators(max, chat),
+            Command::TurnTime(seconds) => self.set_turn_time(seconds),
+            Command::HostIs(target) if self.players.contains_key(&target.0) => {
+                self.host = target;
+                self.send_command(Command::HostIs(target));
+            }
+            _ => (),
+        }
+    }
+    fn start(&mut self) -> Result<(), &'static str> {
+        if self.finished {
+            return Err("The game is over");
+        }
+        if self.players.len() < self.game.min_players() {
+            return Err("Not enough players");
+        }
+        self.history.action(self.host, "START");
+        self.game_event(|game, out| game.on_start(out));
+        Ok(())
+    }
+    fn send_settings(&self) {
+        for setting in self.game.settings() {
+            self.send_command(Command::Setting(setting));
+        }
+    }
+    /// Tells the player's socket to close with the given reason and removes them from the session
+    fn kick(&mut self, pid: PlayerId, reason: String) {
+        if self.is_host(pid) {
+            return;
+        }
+        let reason = if reason.is_empty() { "Kicked by host".to_owned() } else { reason };
+        self.send_to(pid, Command::Kick(pid, reason));
+        if let Some(player) = self.remove(pid) {
+            self.notice(Notice::Kicked(player.name));
+        }
+    }
+    fn rename(&mut self, pid: PlayerId, name: String) -> Result<(), NameError> {
+        self.check_name(&name, Some(pid))?;
+        if let Some(player) = self.players.get_mut(&pid.0) {
+            self.game.set_name(pid, &name);
+            player.name = name;
+            self.unsaved = true;
+            self.send_command(self.roster());
+        } else if let Some(spectator) = self.spectators.get_mut(&pid.0) {
+            spectator.name = name;
+            self.send_command(self.spectator_roster());
+        }
+        Ok(())
+    }
+    /// Changes how many spectators may watch and whether they can chat
+    ///
+    /// Spectators already watching are kept even if there now are more than allowed.
+    fn set_spectators(&mut self, max: u32, chat: bool) {
+        self.max_spectators = max;
+        self.spectator_chat = chat;
+        self.send_command(Command::SpectatorSettings { max, chat });
+    }
+    /// How the session shows up in the lobby, if it's public
+    fn listing(&self, code: u16) -> Option<Listing> {
+        if !self.public {
+            return None;
+        }
+        let max_players = self.game.max_players();
+        Some(Listing {
+            code: format!("{code:04X}"),
+            game: self.game.id(),
+            rules: self.game.settings().into_iter().map(|s| (s.key, s.value)).collect(),
+            host: self.players.get(&self.host.0).map(|p| p.name.clone()).unwrap_or_default(),
+            seats_taken: self.players.len(),
+            seats_free: (max_players != usize::MAX).then(|| max_players.saturating_sub(self.players.len())),
+            spectators: self.spectators.len(),
+            spectators_allowed: self.spectators.len() < self.max_spectators as usize,
+            locked: self.locked,
+            password: self.password_hash.is_some(),
+        })
+    }
+    fn summary(&self, code: u16) -> SessionSummary {
+        let seats = |seats: &BTreeMap<u32, Player>| seats.iter()
+            .map(|(&id, p)| Seat { id: PlayerId(id), name: p.name.clone(), bot: p.bot, connected: p.bot || !p.tx.is_closed() })
+            .collect();
+        SessionSummary {
+            code: format!("{code:04X}"),
+            game: self.game.id(),
+            host: self.host,
+            players: seats(&self.players),
+            spectators: seats(&self.spectators),
+            public: self.public,
+            locked: self.locked,
+            age: self.opened.elapsed().as_secs(),
+            idle: self.last_active.elapsed().as_secs(),
+        }
+    }
+    fn details(&self, code: u16) -> SessionDetails {
+        SessionDetails {
+            summary: self.summary(code),
+            rules: self.game.settings().into_iter().map(|s| (s.key, s.value)).collect(),
+            turn: self.game.turn(),
+            next_round: self.history.next_round(),
+            scores: self.scores.clone(),
+            chat: self.chat_history.iter().map(|cmd| cmd.to_string()).collect(),
+            state: self.game.save().unwrap_or_default(),
+        }
+    }
+    /// Sends everyone away with the reason given, which ends the session
+    fn close(&mut self, reason: String) {
+        for (&id, player) in self.players.iter().chain(&self.spectators) {
+            let _ = player.tx.send(Command::Kick(PlayerId(id), reason.clone()));
+        }
+        self.players.clear();
+        self.spectators.clear();
+        // A restored session would otherwise keep waiting for its players
+        self.abandon_at = None;
+    }
+    fn roster(&self) -> Command {
+        Command::Players(self.players.iter().map(|(&id, p)| (PlayerId(id), p.name.clone())).collect())
+    }
+    fn spectator_roster(&self) -> Command {
+        Command::Watchers(self.spectators.iter().map(|(&id, p)| (PlayerId(id), p.name.clone())).collect())
+    }
+}
+
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::games::RoundResult;
+
+use super::Outcome;
+
+/// Rounds played and how they went, sent as `<hands> <wins> <losses> <draws> <net>`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    pub hands: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub net: i64,
+}
+
+impl Tally {
+    pub fn add(&mut self, result: &RoundResult) {
+        self.hands += 1;
+        match result.outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Lose => self.losses += 1,
+            Outcome::Draw => self.draws += 1,
+        }
+        self.net += result.net;
+    }
+}
+
+impl FromStr for Tally {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split(' ');
+        let mut next = || split.next().ok_or(());
+        Ok(Tally {
+            hands: next()?.parse().map_err(|_| ())?,
+            wins: next()?.parse().map_err(|_| ())?,
+            losses: next()?.parse().map_err(|_| ())?,
+            draws: next()?.parse().map_err(|_| ())?,
+            net: next()?.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+impl Display for Tally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Tally { hands, wins, losses, draws, net } = self;
+        write!(f, "{hands} {wins} {losses} {draws} {net}")
+    }
+}
+
+/// Everything a player has played, across sessions
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PlayerStats {
+    #[serde(flatten)]
+    pub tally: Tally,
+    pub blackjacks: u32,
+    pub biggest_win: i64,
+    /// How many times the player has ended a round with each role
+    pub roles: BTreeMap<String, u32>,
+}
+
+impl PlayerStats {
+    pub fn add(&mut self, result: &RoundResult) {
+        self.tally.add(result);
+        if result.blackjack {
+            self.blackjacks += 1;
+        }
+        self.biggest_win = self.biggest_win.max(result.net);
+        if let Some(role) = &result.role {
+            *self.roles.entry(role.clone()).or_default() += 1;
+        }
+    }
+}
+
+/// A line of the leaderboard served from `/stats`
+#[derive(Debug, Serialize)]
+pub struct Standing {
+    pub name: String,
+    #[serde(flatten)]
+    pub stats: PlayerStats,
+}
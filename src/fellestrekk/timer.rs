@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+use super::PlayerId;
+
+/// Seconds a player gets to act until the host says otherwise
+pub const DEFAULT_TURN_TIME: u32 = 30;
+
+/// The clock on whoever's turn it is
+///
+/// The session's task sleeps until the [deadline](TurnTimer::deadline) alongside waiting for messages.
+#[derive(Debug)]
+pub struct TurnTimer {
+    /// Seconds per turn, 0 turns the timer off
+    pub turn_time: u32,
+    turn: Option<PlayerId>,
+    deadline: Option<Instant>,
+}
+
+impl TurnTimer {
+    pub fn new() -> Self {
+        TurnTimer {
+            turn_time: DEFAULT_TURN_TIME,
+            turn: None,
+            deadline: None,
+        }
+    }
+    /// Whose turn the clock was last started for
+    pub fn turn(&self) -> Option<PlayerId> {
+        self.turn
+    }
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+    /// Starts the clock for the given player (or stops it), returns how many seconds they've got
+    pub fn start(&mut self, pid: Option<PlayerId>) -> Option<u32> {
+        self.turn = pid;
+        self.deadline = pid
+            .filter(|_| self.turn_time != 0)
+            .map(|_| Instant::now() + Duration::from_secs(self.turn_time.into()));
+        self.deadline.map(|_| self.turn_time)
+    }
+    /// Stops the clock if it has run out, returning whose turn it was
+    pub fn expire(&mut self) -> Option<PlayerId> {
+        match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => {
+                self.deadline = None;
+                self.turn
+            }
+            _ => None,
+        }
+    }
+}
@@ -1,17 +1,441 @@
-use crate::fellestrekk::{Command, CommandQueue, PlayerId};
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::str::FromStr;
 
-pub trait Game {
-    fn has_space(&self) -> bool {
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::fellestrekk::{Bot, Difficulty, Notice, Outcome, PlayerId};
+
+/// A game played in a session
+///
+/// The session calls the `on_*` hooks as things happen at the table and hands the game its players' actions.
+/// Whatever the game has to say goes into the [`EventQueue`] it's given.
+/// Games are serialisable so sessions can be saved between rounds and picked up again after a restart.
+pub trait Game: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The namespace the game's actions and events are sent under
+    const ID: GameId;
+    /// What a player can ask of the game, parsed from their messages
+    type Action: FromStr + Debug;
+    /// What the game tells its players and spectators
+    type Event: Display + Debug;
+
+    /// Fewest players needed for the host to start the game
+    fn min_players(&self) -> usize {
+        1
+    }
+    /// Most players the game seats, anyone else has to spectate
+    fn max_players(&self) -> usize {
+        1
+    }
+    /// The settings the host can change, with their current values
+    fn settings(&self) -> Vec<Setting> {
+        Vec::new()
+    }
+    /// Changes a setting, the value has already been checked against [`Game::settings`]
+    fn configure(&mut self, _key: &str, _value: &str) -> Result<(), SettingError> {
+        Err(SettingError::Unknown)
+    }
+    /// Makes the game deal the same way every time it's seeded the same, so what happens can be predicted in tests
+    fn seed(&mut self, _seed: u64) {}
+    fn on_join(&mut self, _pid: PlayerId, _events: EventQueue<Self::Event>) {}
+    fn on_leave(&mut self, _pid: PlayerId, _events: EventQueue<Self::Event>) {}
+    /// How many chips the player's wallet holds, `None` for a player without one who can bet what they like
+    fn set_bankroll(&mut self, _pid: PlayerId, _chips: Option<u64>) {}
+    /// The name the player goes by, once they've joined and whenever they change it
+    fn set_name(&mut self, _pid: PlayerId, _name: &str) {}
+    /// The host wants to start the game (or its next round), there are at least [`Game::min_players`] players
+    fn on_start(&mut self, _events: EventQueue<Self::Event>) {}
+    /// The player whose turn it is ran out of time, make the default move for them
+    fn on_timer(&mut self, _pid: PlayerId, _events: EventQueue<Self::Event>) {}
+    fn handle(&mut self, _pid: PlayerId, _action: Self::Action, _events: EventQueue<Self::Event>) {}
+    /// Lets the game move along on its own, called again for as long as it returns true
+    fn tick(&mut self, _events: EventQueue<Self::Event>) -> bool {
         false
     }
-    fn tick(&mut self, _cmds: CommandQueue) -> bool {
+    /// Whose move the game is waiting on, if anyone's
+    fn turn(&self) -> Option<PlayerId> {
+        None
+    }
+    /// Whether the game has ended, it gets no more actions after this
+    fn is_finished(&self) -> bool {
         false
     }
-    fn handle(&mut self, _pid: PlayerId, _cmd: Command, _cmds: CommandQueue) {}
+    /// Whether nothing is in play, which is when the session saves the game
+    fn between_rounds(&self) -> bool {
+        self.turn().is_none()
+    }
+}
+
+/// A [`Game`] with its action and event types erased, so a session can hold any game
+///
+/// Actions come in as text and events go out as text in an [`Outbox`].
+pub trait DynGame: Send + Sync {
+    fn id(&self) -> GameId;
+    fn min_players(&self) -> usize;
+    fn max_players(&self) -> usize;
+    fn settings(&self) -> Vec<Setting>;
+    /// Checks the value against the game's settings before passing it on
+    fn configure(&mut self, key: &str, value: &str) -> Result<(), SettingError>;
+    fn seed(&mut self, seed: u64);
+    fn on_join(&mut self, pid: PlayerId, out: &mut Outbox);
+    fn on_leave(&mut self, pid: PlayerId, out: &mut Outbox);
+    fn set_bankroll(&mut self, pid: PlayerId, chips: Option<u64>);
+    fn set_name(&mut self, pid: PlayerId, name: &str);
+    fn on_start(&mut self, out: &mut Outbox);
+    fn on_timer(&mut self, pid: PlayerId, out: &mut Outbox);
+    /// Parses and handles an action, returns false if the game didn't understand it
+    fn handle(&mut self, pid: PlayerId, action: &str, out: &mut Outbox) -> bool;
+    fn tick(&mut self, out: &mut Outbox) -> bool;
+    fn turn(&self) -> Option<PlayerId>;
+    fn is_finished(&self) -> bool;
+    fn between_rounds(&self) -> bool;
+    fn save(&self) -> serde_json::Result<Value>;
 }
 
+impl<G: Game> DynGame for G {
+    fn id(&self) -> GameId {
+        G::ID
+    }
+    fn min_players(&self) -> usize {
+        Game::min_players(self)
+    }
+    fn max_players(&self) -> usize {
+        Game::max_players(self)
+    }
+    fn settings(&self) -> Vec<Setting> {
+        Game::settings(self)
+    }
+    fn configure(&mut self, key: &str, value: &str) -> Result<(), SettingError> {
+        let setting = Game::settings(self).into_iter()
+            .find(|s| s.key == key)
+            .ok_or(SettingError::Unknown)?;
+        if !setting.kind.accepts(value) {
+            return Err(SettingError::Invalid);
+        }
+        Game::configure(self, key, value)
+    }
+    fn seed(&mut self, seed: u64) {
+        Game::seed(self, seed)
+    }
+    fn on_join(&mut self, pid: PlayerId, out: &mut Outbox) {
+        Game::on_join(self, pid, EventQueue::new(out))
+    }
+    fn on_leave(&mut self, pid: PlayerId, out: &mut Outbox) {
+        Game::on_leave(self, pid, EventQueue::new(out))
+    }
+    fn set_bankroll(&mut self, pid: PlayerId, chips: Option<u64>) {
+        Game::set_bankroll(self, pid, chips)
+    }
+    fn set_name(&mut self, pid: PlayerId, name: &str) {
+        Game::set_name(self, pid, name)
+    }
+    fn on_start(&mut self, out: &mut Outbox) {
+        Game::on_start(self, EventQueue::new(out))
+    }
+    fn on_timer(&mut self, pid: PlayerId, out: &mut Outbox) {
+        Game::on_timer(self, pid, EventQueue::new(out))
+    }
+    fn handle(&mut self, pid: PlayerId, action: &str, out: &mut Outbox) -> bool {
+        match action.parse() {
+            Ok(action) => {
+                Game::handle(self, pid, action, EventQueue::new(out));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+    fn tick(&mut self, out: &mut Outbox) -> bool {
+        Game::tick(self, EventQueue::new(out))
+    }
+    fn turn(&self) -> Option<PlayerId> {
+        Game::turn(self)
+    }
+    fn is_finished(&self) -> bool {
+        Game::is_finished(self)
+    }
+    fn between_rounds(&self) -> bool {
+        Game::between_rounds(self)
+    }
+    fn save(&self) -> serde_json::Result<Value> {
+        serde_json::to_value(self)
+    }
+}
+
+/// Who a queued event should be delivered to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Recipient {
+    /// Public, sent to every player and spectator
+    All,
+    /// Private to a single player, e.g. their hole cards
+    Player(PlayerId),
+}
+
+/// Something a game wants delivered, the session wraps it in the right command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameOutput {
+    Event(String),
+    Notice(Notice),
+    /// Only written down in the hand history, never sent
+    Log(String),
+    /// How the round went for the player it's addressed to, counted in their statistics
+    Result(RoundResult),
+    /// Why the player it's addressed to couldn't do what they asked
+    Error(String),
+}
+
+/// A player's result at the end of a round
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundResult {
+    pub outcome: Outcome,
+    /// Money won (or lost, if negative) over the round
+    pub net: i64,
+    /// Chips the player bet over the round, side bets included
+    pub wagered: u64,
+    /// Chips paid back to the player, stakes returned included
+    pub paid_out: u64,
+    /// Whether the player was dealt a natural blackjack
+    pub blackjack: bool,
+    /// The role the player ended up with, in games that hand them out (like Arsehole's president)
+    pub role: Option<String>,
+}
+
+pub type Outbox = Vec<(Recipient, GameOutput)>;
+
+/// Where a game puts the events it wants delivered
+pub struct EventQueue<'a, E> {
+    inner: &'a mut Outbox,
+    event: PhantomData<E>,
+}
+
+impl<'a, E: Display> EventQueue<'a, E> {
+    fn new<'b: 'a>(inner: &'b mut Outbox) -> EventQueue<'a, E> {
+        Self { inner, event: PhantomData }
+    }
+    pub(crate) fn send(&mut self, event: E) {
+        self.inner.push((Recipient::All, GameOutput::Event(event.to_string())));
+    }
+    pub(crate) fn send_to(&mut self, pid: PlayerId, event: E) {
+        self.inner.push((Recipient::Player(pid), GameOutput::Event(event.to_string())));
+    }
+    /// Posts a line in the session's chat
+    pub(crate) fn notice(&mut self, notice: Notice) {
+        self.inner.push((Recipient::All, GameOutput::Notice(notice)));
+    }
+    /// Writes something down in the hand history without telling anyone
+    pub(crate) fn log(&mut self, line: String) {
+        self.inner.push((Recipient::All, GameOutput::Log(line)));
+    }
+    /// Tells a player why what they asked for didn't happen
+    pub(crate) fn error(&mut self, pid: PlayerId, message: String) {
+        self.inner.push((Recipient::Player(pid), GameOutput::Error(message)));
+    }
+    /// Reports how the round went for a player
+    pub(crate) fn result(&mut self, pid: PlayerId, result: RoundResult) {
+        self.inner.push((Recipient::Player(pid), GameOutput::Result(result)));
+    }
+    pub(crate) fn reborrow<'b>(&'b mut self) -> EventQueue<'b, E> {
+        EventQueue {
+            inner: self.inner,
+            event: PhantomData,
+        }
+    }
+}
+
+/// What values a game setting takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingKind {
+    Toggle,
+    Number { min: u32, max: u32 },
+    Choice(Vec<String>),
+}
+
+impl SettingKind {
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            SettingKind::Toggle => matches!(value, "ON" | "OFF"),
+            SettingKind::Number { min, max } => value.parse().is_ok_and(|n: u32| (*min..=*max).contains(&n)),
+            SettingKind::Choice(options) => options.iter().any(|o| o == value),
+        }
+    }
+}
+
+impl FromStr for SettingKind {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, args) = s.split_once(':').unwrap_or((s, ""));
+        Ok(match kind {
+            "TOGGLE" => SettingKind::Toggle,
+            "NUMBER" => {
+                let (min, max) = args.split_once('-').ok_or(())?;
+                SettingKind::Number {
+                    min: min.parse().map_err(|_| ())?,
+                    max: max.parse().map_err(|_| ())?,
+                }
+            }
+            "CHOICE" => SettingKind::Choice(args.split(',').map(|s| s.to_owned()).collect()),
+            _ => return Err(()),
+        })
+    }
+}
+
+impl Display for SettingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingKind::Toggle => write!(f, "TOGGLE"),
+            SettingKind::Number { min, max } => write!(f, "NUMBER:{min}-{max}"),
+            SettingKind::Choice(options) => write!(f, "CHOICE:{}", options.join(",")),
+        }
+    }
+}
+
+/// One entry in a game's settings schema, sent as `SETTING <key> <kind> <value>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub key: String,
+    pub kind: SettingKind,
+    pub value: String,
+}
+
+impl FromStr for Setting {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split(' ');
+        Ok(Setting {
+            key: split.next().ok_or(())?.to_owned(),
+            kind: split.next().ok_or(())?.parse()?,
+            value: split.next().ok_or(())?.to_owned(),
+        })
+    }
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.key, self.kind, self.value)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SettingError {
+    Unknown,
+    Invalid,
+    /// The setting can't be changed right now, e.g. in the middle of a round
+    InProgress,
+}
+
+impl Display for SettingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingError::Unknown => write!(f, "No such setting"),
+            SettingError::Invalid => write!(f, "Invalid value"),
+            SettingError::InProgress => write!(f, "Can't change that mid-game"),
+        }
+    }
+}
+
+/// The action and event type of games that don't have any
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Nothing {}
+
+impl FromStr for Nothing {
+    type Err = ();
+    fn from_str(_: &str) -> Result<Self, Self::Err> {
+        Err(())
+    }
+}
+
+impl Display for Nothing {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {}
+    }
+}
+
+/// The games a session can be hosted for
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GameId {
+    Blackjack,
+    Chatroom,
+}
+
+impl GameId {
+    pub fn new_game(&self) -> Box<dyn DynGame> {
+        match self {
+            GameId::Blackjack => Box::new(Blackjack::new()),
+            GameId::Chatroom => Box::new(Chatroom),
+        }
+    }
+    /// A computer-controlled player for the game, if it has any
+    pub fn bot(&self, difficulty: Difficulty) -> Option<Box<dyn Bot>> {
+        match self {
+            GameId::Blackjack => Some(Box::new(BlackjackBot::new(difficulty))),
+            GameId::Chatroom => None,
+        }
+    }
+    /// What the game is called on the wire
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameId::Blackjack => "BLACKJACK",
+            GameId::Chatroom => "CHATROOM",
+        }
+    }
+    /// What a command to or from the game is counted as in the metrics, by the word its payload starts with
+    ///
+    /// Anything the game doesn't know is counted together, so clients can't make up labels of their own.
+    pub fn command_kind(&self, payload: &str) -> &'static str {
+        let word = payload.split(' ').next().unwrap_or_default();
+        let (kinds, other): (&[(&str, &'static str)], _) = match self {
+            GameId::Blackjack => (blackjack::COMMAND_KINDS, "BLACKJACK_OTHER"),
+            GameId::Chatroom => (&[], "CHATROOM_OTHER"),
+        };
+        kinds.iter().find(|&&(w, _)| w == word).map_or(other, |&(_, kind)| kind)
+    }
+    /// Picks a game back up from what [`DynGame::save`] gave
+    pub fn load(&self, state: Value) -> serde_json::Result<Box<dyn DynGame>> {
+        Ok(match self {
+            GameId::Blackjack => Box::new(serde_json::from_value::<Blackjack>(state)?),
+            GameId::Chatroom => Box::new(serde_json::from_value::<Chatroom>(state)?),
+        })
+    }
+}
+
+impl FromStr for GameId {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "BLACKJACK" => GameId::Blackjack,
+            "CHATROOM" => GameId::Chatroom,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl Display for GameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Empty;
-impl Game for Empty {}
+impl Game for Empty {
+    const ID: GameId = GameId::Chatroom;
+    type Action = Nothing;
+    type Event = Nothing;
+}
+
+/// A session without a game, just players talking
+#[derive(Serialize, Deserialize)]
+pub struct Chatroom;
+impl Game for Chatroom {
+    const ID: GameId = GameId::Chatroom;
+    type Action = Nothing;
+    type Event = Nothing;
+
+    fn max_players(&self) -> usize {
+        usize::MAX
+    }
+}
 
 mod blackjack;
 pub use self::blackjack::*;
@@ -1,16 +1,69 @@
 use std::cmp::Ordering::{Equal, Greater, Less};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
 
-use crate::{card::{Card, Deck}, dealer::Dealer, fellestrekk::{Command, CommandQueue, PlayerId}, hand::{BlackjackExt, Hand}};
-use super::Game;
+use collect_result::CollectResult;
 
-#[derive(Debug, Clone)]
+use crate::{card::{Card, Deck}, dealer::Dealer, fellestrekk::{Notice, Outcome, PlayerId}, hand::{BlackjackExt, Hand}};
+use crate::counting::{Count, CountSystem};
+use crate::strategy::{self, Play};
+use super::{EventQueue, Game, GameId, RoundResult, Setting, SettingError, SettingKind};
+
+mod bot;
+mod side_bets;
+
+pub use bot::BlackjackBot;
+pub use side_bets::{Paytable, SideBet, SideHand};
+
+type Events<'a> = EventQueue<'a, BlackjackEvent>;
+
+/// How likely the counting trainer is to quiz the players before a round
+const QUIZ_CHANCE: f64 = 1. / 3.;
+/// How many players can sit at the table, a single deck doesn't go around many more
+const MAX_SEATS: usize = 4;
+/// The most a hand can be bet, which keeps it well within range doubled and paid out as a blackjack
+const MAX_BET: u32 = 100_000;
+/// The most each side bet can be, it pays up to [`side_bets::MAX_ODDS`] to one
+const MAX_SIDE_BET: u32 = 10_000;
+/// What actions and events are counted as in the metrics, by the word they start with
+pub(super) const COMMAND_KINDS: &[(&str, &str)] = &[
+    ("BET", "BLACKJACK_BET"), ("STAND", "BLACKJACK_STAND"), ("HIT", "BLACKJACK_HIT"),
+    ("DOUBLEDOWN", "BLACKJACK_DOUBLEDOWN"), ("SURRENDER", "BLACKJACK_SURRENDER"), ("SPLIT", "BLACKJACK_SPLIT"),
+    ("COUNT", "BLACKJACK_COUNT"), ("START", "BLACKJACK_START"), ("SEAT", "BLACKJACK_SEAT"), ("TAKEMONEY", "BLACKJACK_TAKEMONEY"),
+    ("SENDMONEY", "BLACKJACK_SENDMONEY"), ("REVEALDOWNS", "BLACKJACK_REVEALDOWNS"),
+    ("PLAYERDRAW", "BLACKJACK_PLAYERDRAW"), ("DECKSIZE", "BLACKJACK_DECKSIZE"), ("DEALERDRAW", "BLACKJACK_DEALERDRAW"),
+    ("DOWNCARD", "BLACKJACK_DOWNCARD"), ("VALUEUPDATE", "BLACKJACK_VALUEUPDATE"), ("STATUS", "BLACKJACK_STATUS"),
+    ("WIN", "BLACKJACK_WIN"), ("LOSE", "BLACKJACK_LOSE"), ("DRAW", "BLACKJACK_DRAW"), ("HINT", "BLACKJACK_HINT"),
+    ("QUIZ", "BLACKJACK_QUIZ"), ("SIDEBET", "BLACKJACK_SIDEBET"), ("SIDEWIN", "BLACKJACK_SIDEWIN"),
+    ("SIDELOSE", "BLACKJACK_SIDELOSE"), ("COUNTED", "BLACKJACK_COUNTED"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Blackjack {
     deck: Deck,
     dealer_hand: Hand,
-    player_hand: Hand,
     dealer: Dealer,
+    /// Everyone at the table, in the order they're dealt to
+    #[serde(default)]
+    seats: Vec<Seat>,
+    /// The seat whose move it is, `None` when it's the dealer's
     turn: Option<usize>,
-    bet: u32,
+    /// What the shoe in use was shuffled with, only logged once the shoe is replaced
+    #[serde(default)]
+    seed: Option<u64>,
+    /// What every shoe is shuffled with instead of a random seed, see [`Game::seed`]
+    #[serde(default)]
+    fixed_seed: Option<u64>,
+    /// The card counting trainer, if the host turned it on
+    #[serde(default)]
+    trainer: Option<Trainer>,
+    /// What Perfect Pairs pays, `None` if the host turned it off
+    #[serde(default)]
+    pairs_paytable: Option<Paytable>,
+    /// What 21+3 pays, `None` if the host turned it off
+    #[serde(default)]
+    poker_paytable: Option<Paytable>,
 
     dirty_deck: bool,
     game_over: bool,
@@ -18,12 +71,455 @@ pub struct Blackjack {
     due_for_tick: bool,
 }
 
+/// A player's place at the table
+///
+/// A seat is dealt in when there's a bet on it as the round starts, its hand stays empty otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Seat {
+    pid: PlayerId,
+    hand: Hand,
+    /// The bet riding on the hand, 0 once it's surrendered
+    bet: u32,
+    /// What the player has put in this round
+    stake: u32,
+    /// What was given back on a surrender this round
+    refunded: u32,
+    /// Side bets placed for the coming round
+    side_bets: Vec<(SideBet, u32)>,
+    /// What the side bets paid out this round
+    side_paid: u32,
+    /// What the player's wallet holds, `None` if they play without one
+    #[serde(default)]
+    bankroll: Option<u64>,
+    /// What the player goes by, to label the seat with
+    #[serde(default)]
+    name: String,
+}
+
+impl Seat {
+    fn new(pid: PlayerId) -> Self {
+        Seat { pid, hand: Hand::default(), bet: 0, stake: 0, refunded: 0, side_bets: Vec::new(), side_paid: 0, bankroll: None, name: String::new() }
+    }
+    fn dealt_in(&self) -> bool {
+        !self.hand.cards().is_empty()
+    }
+    /// What the player's stake would come to with `chips` more, as long as their wallet holds them
+    fn stake_with(&self, chips: u32) -> Result<u32, &'static str> {
+        let stake = self.stake.checked_add(chips).ok_or("That's more than the table takes")?;
+        if self.bankroll.is_some_and(|bankroll| u64::from(stake) > bankroll) {
+            return Err("Not enough chips in your wallet");
+        }
+        Ok(stake)
+    }
+    /// Puts more of the player's chips on the table for this round, as long as their wallet holds them
+    fn put_in(&mut self, chips: u32) -> Result<(), &'static str> {
+        self.stake = self.stake_with(chips)?;
+        Ok(())
+    }
+    /// Pays the hand out against the dealer's at the end of the round
+    fn settle(&mut self, dealer_hand: &Hand, mut cmds: Events) {
+        let pid = self.pid;
+        let bet = std::mem::take(&mut self.bet);
+        let outcome = match self.hand.cmp(dealer_hand) {
+            // a surrendered hand is lost whatever the dealer ends up with
+            _ if bet == 0 => Outcome::Lose,
+            Less => Outcome::Lose,
+            Greater => Outcome::Win,
+            Equal => Outcome::Draw,
+        };
+        cmds.notice(Notice::Result(pid, outcome));
+        let payout = match outcome {
+            Outcome::Lose => {
+                cmds.send_to(pid, BlackjackEvent::Lose);
+                0
+            }
+            Outcome::Win => {
+                cmds.send_to(pid, BlackjackEvent::Win);
+                // blackjack bonus
+                let payout = if self.hand.is_natural() { bet * 2 + bet / 2 } else { bet * 2 };
+                cmds.send_to(pid, BlackjackEvent::SendMoney(payout));
+                payout
+            }
+            Outcome::Draw => {
+                cmds.send_to(pid, BlackjackEvent::Draw);
+                cmds.send_to(pid, BlackjackEvent::SendMoney(bet));
+                bet
+            }
+        };
+        let stake = std::mem::take(&mut self.stake);
+        let paid_out = payout + std::mem::take(&mut self.side_paid) + std::mem::take(&mut self.refunded);
+        cmds.result(pid, RoundResult {
+            outcome,
+            net: paid_out as i64 - stake as i64,
+            wagered: stake.into(),
+            paid_out: paid_out.into(),
+            blackjack: self.hand.is_natural(),
+            role: None,
+        });
+    }
+}
+
+/// Keeps count of the cards dealt and quizzes the players on it every so often
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Trainer {
+    count: Count,
+    /// How each player is doing, everyone is asked the same question but answers it on their own
+    players: BTreeMap<PlayerId, Quizzed>,
+}
+
+/// A player's open question and their score so far
+///
+/// A question that's still open when the next one is asked counts as answered wrong.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Quizzed {
+    /// The open question and its answer
+    quiz: Option<(QuizKind, i32)>,
+    asked: u32,
+    correct: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuizKind {
+    Running,
+    /// Rounded to the nearest whole number
+    True,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlackjackAction {
+    Bet(u32),
+    Stand,
+    Hit,
+    DoubleDown,
+    Surrender,
+    Split,
+    /// Asks what basic strategy would play
+    Hint,
+    /// Answers the counting trainer's question
+    Count(i32),
+    SideBet(SideBet, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlackjackEvent {
+    Start,
+    /// Who is sitting in a seat dealt in this round
+    Seat(PlayerId, String),
+    DeckSize(u8),
+    PlayerDraw(PlayerId, Card),
+    DealerDraw(Card),
+    DownCard(Card),
+    RevealDowns(Card, Vec<Card>),
+    ValueUpdate(Option<PlayerId>, u8, bool),
+    Status{
+        hit: bool,
+        stand: bool,
+        double: bool,
+        surrender: bool,
+        split: bool,
+        new_game: bool,
+    },
+    SendMoney(u32),
+    TakeMoney(u32),
+    Win,
+    Lose,
+    Draw,
+    Hint(Play),
+    Quiz(QuizKind),
+    SideBet(SideBet, u32),
+    SideWin(SideBet, SideHand, u32),
+    SideLose(SideBet),
+    Counted {
+        right: bool,
+        answer: i32,
+        correct: u32,
+        asked: u32,
+    },
+}
+
+impl Display for QuizKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuizKind::Running => write!(f, "RUNNING"),
+            QuizKind::True => write!(f, "TRUE"),
+        }
+    }
+}
+
+impl FromStr for QuizKind {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "RUNNING" => Ok(QuizKind::Running),
+            "TRUE" => Ok(QuizKind::True),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for BlackjackAction {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split(' ');
+        match split.next().ok_or(())? {
+            "BET" => Ok(BlackjackAction::Bet(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
+            "STAND" => Ok(BlackjackAction::Stand),
+            "HIT" => Ok(BlackjackAction::Hit),
+            "DOUBLEDOWN" => Ok(BlackjackAction::DoubleDown),
+            "SURRENDER" => Ok(BlackjackAction::Surrender),
+            "SPLIT" => Ok(BlackjackAction::Split),
+            "HINT" => Ok(BlackjackAction::Hint),
+            "COUNT" => Ok(BlackjackAction::Count(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
+            "SIDEBET" => Ok(BlackjackAction::SideBet(
+                split.next().ok_or(())?.parse()?,
+                split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+            )),
+            _ => Err(())
+        }
+    }
+}
+
+impl Display for BlackjackAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlackjackAction::Bet(i) => write!(f, "BET {i}"),
+            BlackjackAction::Stand => write!(f, "STAND"),
+            BlackjackAction::Hit => write!(f, "HIT"),
+            BlackjackAction::DoubleDown => write!(f, "DOUBLEDOWN"),
+            BlackjackAction::Surrender => write!(f, "SURRENDER"),
+            BlackjackAction::Split => write!(f, "SPLIT"),
+            BlackjackAction::Hint => write!(f, "HINT"),
+            BlackjackAction::Count(i) => write!(f, "COUNT {i}"),
+            BlackjackAction::SideBet(bet, i) => write!(f, "SIDEBET {bet} {i}"),
+        }
+    }
+}
+
+impl FromStr for BlackjackEvent {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.split(' ');
+        match split.next().ok_or(())? {
+            "START" => Ok(BlackjackEvent::Start),
+            "SEAT" => Ok(BlackjackEvent::Seat(
+                split.next().ok_or(())?.parse().map_err(|_| ())?,
+                split.next().ok_or(())?.to_owned(),
+            )),
+            "TAKEMONEY" => Ok(BlackjackEvent::TakeMoney(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
+            "SENDMONEY" => Ok(BlackjackEvent::SendMoney(split.next().and_then(|s| s.parse().ok()).ok_or(())?)),
+            "REVEALDOWNS" => Ok(BlackjackEvent::RevealDowns(
+                split.next().ok_or(())?.parse()?,
+                split.map(|c| c.parse()).collect_result::<Vec<_>>()?,
+            )),
+            "PLAYERDRAW" => Ok(BlackjackEvent::PlayerDraw(
+                split.next().ok_or(())?.parse().map_err(|_| ())?,
+                split.next().ok_or(())?.parse()?,
+            )),
+            "DECKSIZE" => Ok(BlackjackEvent::DeckSize(
+                split.next().ok_or(())?.parse().map_err(|_| ())?
+            )),
+            "DEALERDRAW" => Ok(BlackjackEvent::DealerDraw(
+                split.next().ok_or(())?.parse()?
+            )),
+            "DOWNCARD" => Ok(BlackjackEvent::DownCard(
+                split.next().ok_or(())?.parse()?,
+            )),
+            "VALUEUPDATE" => {
+                let mut iter = split.rev();
+                let last = iter.next().ok_or(())?;
+                let soft = last == "soft";
+                let value = if soft {
+                    iter.next().ok_or(())?
+                } else {
+                    last
+                }.parse().map_err(|_| ())?;
+                let pn = iter.next().and_then(|pn| pn.parse().ok());
+                Ok(BlackjackEvent::ValueUpdate(pn, value, soft))
+            }
+            "STATUS" => {
+                let iter = split;
+                let mut hit = false;
+                let mut stand = false;
+                let mut double = false;
+                let mut surrender = false;
+                let mut split = false;
+                let mut new_game = false;
+
+                for s in iter {
+                    match s {
+                        "H" => hit = true,
+                        "S" => stand = true,
+                        "D" => double = true,
+                        "U" => surrender = true,
+                        "P" => split = true,
+                        "N" => new_game = true,
+                        _ => return Err(()),
+                    }
+                }
+                Ok(BlackjackEvent::Status { hit, stand, double, surrender, split, new_game })
+            }
+            "WIN" => Ok(BlackjackEvent::Win),
+            "LOSE" => Ok(BlackjackEvent::Lose),
+            "DRAW" => Ok(BlackjackEvent::Draw),
+            "HINT" => Ok(BlackjackEvent::Hint(split.next().ok_or(())?.parse()?)),
+            "QUIZ" => Ok(BlackjackEvent::Quiz(split.next().ok_or(())?.parse()?)),
+            "SIDEBET" => Ok(BlackjackEvent::SideBet(
+                split.next().ok_or(())?.parse()?,
+                split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+            )),
+            "SIDEWIN" => Ok(BlackjackEvent::SideWin(
+                split.next().ok_or(())?.parse()?,
+                split.next().ok_or(())?.parse()?,
+                split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+            )),
+            "SIDELOSE" => Ok(BlackjackEvent::SideLose(split.next().ok_or(())?.parse()?)),
+            "COUNTED" => {
+                let right = match split.next().ok_or(())? {
+                    "RIGHT" => true,
+                    "WRONG" => false,
+                    _ => return Err(()),
+                };
+                Ok(BlackjackEvent::Counted {
+                    right,
+                    answer: split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+                    correct: split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+                    asked: split.next().and_then(|s| s.parse().ok()).ok_or(())?,
+                })
+            }
+            _ => Err(())
+        }
+    }
+}
+
+impl Display for BlackjackEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlackjackEvent::Start => write!(f, "START"),
+            BlackjackEvent::Seat(p, name) => write!(f, "SEAT {p} {name}"),
+            BlackjackEvent::TakeMoney(i) => write!(f, "TAKEMONEY {i}"),
+            BlackjackEvent::SendMoney(i) => write!(f, "SENDMONEY {i}"),
+            BlackjackEvent::PlayerDraw(p, c) => write!(f, "PLAYERDRAW {p} {c}"),
+            BlackjackEvent::DeckSize(n) => write!(f, "DECKSIZE {n}"),
+            BlackjackEvent::DealerDraw(c) => write!(f, "DEALERDRAW {c}"),
+            BlackjackEvent::DownCard(c) => write!(f, "DOWNCARD {c}"),
+            BlackjackEvent::RevealDowns(c, ps) => {
+                write!(f, "REVEALDOWNS {c}")?;
+                for p in ps {
+                    write!(f, " {p}")?;
+                }
+                Ok(())
+            }
+            &BlackjackEvent::ValueUpdate(pn, value, soft) => {
+                write!(f, "VALUEUPDATE")?;
+                if let Some(pid) = pn {
+                    write!(f, " {pid}")?;
+                }
+                write!(f, " {value}")?;
+                if soft {
+                    write!(f, " soft")?;
+                }
+                Ok(())
+            }
+            &BlackjackEvent::Status { hit, stand, double, surrender, split, new_game } => {
+                write!(f, "STATUS")?;
+                if hit {
+                    write!(f, " H")?;
+                }
+                if stand {
+                    write!(f, " S")?;
+                }
+                if double {
+                    write!(f, " D")?;
+                }
+                if surrender {
+                    write!(f, " U")?;
+                }
+                if split {
+                    write!(f, " P")?;
+                }
+                if new_game {
+                    write!(f, " N")?;
+                }
+                Ok(())
+            }
+            BlackjackEvent::Win => write!(f, "WIN"),
+            BlackjackEvent::Lose => write!(f, "LOSE"),
+            BlackjackEvent::Draw => write!(f, "DRAW"),
+            BlackjackEvent::Hint(play) => write!(f, "HINT {play}"),
+            BlackjackEvent::Quiz(kind) => write!(f, "QUIZ {kind}"),
+            BlackjackEvent::SideBet(bet, i) => write!(f, "SIDEBET {bet} {i}"),
+            BlackjackEvent::SideWin(bet, hand, i) => write!(f, "SIDEWIN {bet} {hand} {i}"),
+            BlackjackEvent::SideLose(bet) => write!(f, "SIDELOSE {bet}"),
+            &BlackjackEvent::Counted { right, answer, correct, asked } => {
+                let right = if right { "RIGHT" } else { "WRONG" };
+                write!(f, "COUNTED {right} {answer} {correct} {asked}")
+            }
+        }
+    }
+}
+
 impl Game for Blackjack {
-    fn has_space(&self) -> bool {
-        false
+    const ID: GameId = GameId::Blackjack;
+    type Action = BlackjackAction;
+    type Event = BlackjackEvent;
+
+    fn settings(&self) -> Vec<Setting> {
+        let dealer = if self.dealer == Dealer::h17() { "H17" } else { "S17" };
+        let counting = self.trainer.as_ref().map_or("OFF".to_owned(), |t| t.count.system.to_string());
+        let mut systems = vec!["OFF".to_owned()];
+        systems.extend(CountSystem::ALL.iter().map(|s| s.to_string()));
+        let mut settings = vec![Setting {
+            key: "dealer".to_owned(),
+            kind: SettingKind::Choice(vec!["H17".to_owned(), "S17".to_owned()]),
+            value: dealer.to_owned(),
+        }, Setting {
+            key: "counting".to_owned(),
+            kind: SettingKind::Choice(systems),
+            value: counting,
+        }];
+        for bet in SideBet::ALL {
+            let mut paytables = vec!["OFF".to_owned()];
+            paytables.extend(bet.paytables().iter().map(|&p| p.to_owned()));
+            settings.push(Setting {
+                key: bet.key().to_owned(),
+                kind: SettingKind::Choice(paytables),
+                value: self.paytable(bet).as_ref().map_or("OFF".to_owned(), |p| p.to_string()),
+            });
+        }
+        settings
+    }
+    fn between_rounds(&self) -> bool {
+        self.game_over
+    }
+    fn configure(&mut self, key: &str, value: &str) -> Result<(), SettingError> {
+        if !self.game_over {
+            return Err(SettingError::InProgress);
+        }
+        match (key, value) {
+            ("dealer", "H17") => self.dealer = Dealer::h17(),
+            ("dealer", "S17") => self.dealer = Dealer::s17(),
+            ("counting", "OFF") => self.trainer = None,
+            ("counting", system) => {
+                let system = system.parse().map_err(|_| SettingError::Invalid)?;
+                self.trainer = Some(Trainer { count: Count::new(system, 1), players: BTreeMap::new() });
+                // A fresh shoe next round, so there's a count from the top
+                self.deck = Deck::empty();
+            }
+            (key, value) => {
+                let bet = SideBet::ALL.into_iter().find(|b| b.key() == key).ok_or(SettingError::Unknown)?;
+                *self.paytable_mut(bet) = match value {
+                    "OFF" => None,
+                    paytable => Some(paytable.parse().map_err(|_| SettingError::Invalid)?),
+                };
+            }
+        }
+        Ok(())
     }
-    fn tick(&mut self, mut cmds: CommandQueue) -> bool {
-        if (self.game_over && self.bet == 0) || !self.due_for_tick {
+    fn seed(&mut self, seed: u64) {
+        self.fixed_seed = Some(seed);
+    }
+    fn tick(&mut self, mut cmds: Events) -> bool {
+        if (self.game_over && self.seats.iter().all(|s| s.bet == 0)) || !self.due_for_tick {
             // wait for bet
             return false;
         }
@@ -31,106 +527,264 @@ impl Game for Blackjack {
 
         if self.turn.is_none() && !self.game_over {
             self.set_due_for_tick();
-            cmds.send(Command::RevealDowns(self.dealer_hand.cards()[0], vec![self.player_hand.cards()[0]]));
+            let downs = self.seats.iter().filter(|s| s.dealt_in()).map(|s| s.hand.cards()[0]).collect();
+            cmds.send(BlackjackEvent::RevealDowns(self.dealer_hand.cards()[0], downs));
+            self.seen(self.dealer_hand.cards()[0]);
             while self.dealer.hits(&self.dealer_hand) {
                 let card = self.draw_card();
+                self.seen(card);
                 self.dealer_hand.add_card(card);
-                cmds.send(Command::DealerDraw(card));
+                cmds.send(BlackjackEvent::DealerDraw(card));
             }
-            cmds.send(Command::ValueUpdate(None, self.dealer_hand.value(), self.dealer_hand.is_soft()));
+            cmds.send(BlackjackEvent::ValueUpdate(None, self.dealer_hand.value(), self.dealer_hand.is_soft()));
             self.game_over = true;
-            let bet = self.bet;
-            self.bet = 0;
-            match self.player_hand.cmp(&self.dealer_hand) {
-                Less => cmds.send(Command::Lose),
-                Greater => {
-                    cmds.send(Command::Win);
-                    if self.player_hand.is_natural() {
-                        // blackjack bonus
-                        cmds.send(Command::SendMoney(bet * 2 + bet / 2));
-                    } else {
-                        cmds.send(Command::SendMoney(bet * 2));
-                    }
-                }
-                Equal => {
-                    cmds.send(Command::Draw);
-                    cmds.send(Command::SendMoney(bet));
-                }
+            for seat in self.seats.iter_mut().filter(|s| s.dealt_in()) {
+                seat.settle(&self.dealer_hand, cmds.reborrow());
             }
         }
 
         if self.dirty_deck {
             self.dirty_deck = false;
-            cmds.send(Command::DeckSize(self.deck.size() as u8));
+            cmds.send(BlackjackEvent::DeckSize(self.deck.size() as u8));
         }
         true
     }
-    fn handle(&mut self, pid: PlayerId, cmd: Command, mut cmds: CommandQueue) {
-        match cmd {
-            Command::Bet(bet) => self.bet(pid, bet, cmds),
-            Command::Hit => self.hit(pid, cmds),
-            Command::Stand => self.stand(pid, cmds),
-            Command::DoubleDown => self.double_down(pid, cmds),
-            Command::Surrender => self.surrender(pid, cmds),
-            Command::Split => self.split(pid, cmds),
-            Command::Start => {
-                if self.game_over && self.bet != 0 {
-                    self.set_due_for_tick();
-                    cmds.send(Command::Start);
-                    self.game_over = false;
-                    self.turn = Some(0);
-                    if self.deck.size() < 20 {
-                        self.deck = Deck::new_standard();
-                        self.deck.shuffle();
-                        cmds.send(Command::DeckSize(self.deck.size() as u8));
-                    }
-                    let down_player = self.draw_card();
-                    let down_dealer = self.draw_card();
-                    let open_player = self.draw_card();
-                    let open_dealer = self.draw_card();
-
-                    self.dealer_hand = Hand::new([down_dealer, open_dealer]);
-                    self.player_hand = Hand::new([down_player, open_player]);
-
-                    let hand = Hand::new([open_dealer]);
+    fn max_players(&self) -> usize {
+        MAX_SEATS
+    }
+    fn on_join(&mut self, pid: PlayerId, _cmds: Events) {
+        // Someone taking their seat back after a restart still has it
+        if self.seat(pid).is_none() && self.seats.len() < MAX_SEATS {
+            self.seats.push(Seat::new(pid));
+        }
+    }
+    fn set_bankroll(&mut self, pid: PlayerId, chips: Option<u64>) {
+        if let Some(i) = self.seat(pid) {
+            self.seats[i].bankroll = chips;
+        }
+    }
+    fn set_name(&mut self, pid: PlayerId, name: &str) {
+        if let Some(i) = self.seat(pid) {
+            self.seats[i].name = name.to_owned();
+        }
+    }
+    fn on_leave(&mut self, pid: PlayerId, cmds: Events) {
+        let Some(i) = self.seat(pid) else {
+            return;
+        };
+        self.set_due_for_tick();
+        self.seats.remove(i);
+        if let Some(trainer) = &mut self.trainer {
+            trainer.players.remove(&pid);
+        }
+        match self.turn {
+            // Whatever they had riding on the hand is lost
+            Some(turn) if turn == i => self.next_turn(i, cmds),
+            Some(turn) if turn > i => self.turn = Some(turn - 1),
+            _ => (),
+        }
+    }
+    fn on_start(&mut self, mut cmds: Events) {
+        if !self.game_over || self.seats.iter().all(|s| s.bet == 0) {
+            return;
+        }
+        self.set_due_for_tick();
+        cmds.send(BlackjackEvent::Start);
+        self.game_over = false;
+        for seat in &mut self.seats {
+            seat.hand = Hand::default();
+        }
+        let playing: Vec<usize> = (0..self.seats.len()).filter(|&i| self.seats[i].bet > 0).collect();
+        for &i in &playing {
+            cmds.send(BlackjackEvent::Seat(self.seats[i].pid, self.seats[i].name.clone()));
+        }
+        // Enough cards left for every hand and the dealer's
+        if self.deck.size() < 10 * (playing.len() + 1) {
+            if let Some(seed) = self.seed {
+                cmds.log(format!("SEED {seed}"));
+            }
+            let seed = self.fixed_seed.unwrap_or_else(rand::random);
+            self.seed = Some(seed);
+            self.deck = Deck::new_standard();
+            self.deck.shuffle_seeded(seed);
+            cmds.send(BlackjackEvent::DeckSize(self.deck.size() as u8));
+            if let Some(trainer) = &mut self.trainer {
+                trainer.count = Count::new(trainer.count.system, 1);
+            }
+        }
+        self.quiz(cmds.reborrow());
+        // One round of down cards and one of open cards, the dealer last each time
+        let downs: Vec<Card> = playing.iter().map(|_| self.draw_card()).collect();
+        let down_dealer = self.draw_card();
+        let opens: Vec<Card> = playing.iter().map(|_| self.draw_card()).collect();
+        let open_dealer = self.draw_card();
+        for &card in downs.iter().chain(&opens).chain([&open_dealer]) {
+            self.seen(card);
+        }
 
-                    cmds.send(Command::ValueUpdate(None, hand.value(), hand.is_soft()));
-                    cmds.send(Command::DownCard(down_player));
-                    cmds.send(Command::PlayerDraw(PlayerId::HOST, open_player));
-                    cmds.send(Command::DealerDraw(self.dealer_hand.cards()[1]));
+        self.dealer_hand = Hand::new([down_dealer, open_dealer]);
 
-                    let split = self.player_hand.cards()[0].suit_rank().1 == self.player_hand.cards()[1].suit_rank().1;
+        let hand = Hand::new([open_dealer]);
 
-                    cmds.send(Command::ValueUpdate(Some(PlayerId::HOST), self.player_hand.value(), self.player_hand.is_soft()));
-                    if self.player_hand.is_natural() {
-                        self.stand(PlayerId::HOST, cmds.reborrow());
-                    } else {
-                        cmds.send(Command::Status { hit: true, stand: true, double: true, surrender: true, split, new_game: false });
-                    }
-                }
+        cmds.send(BlackjackEvent::ValueUpdate(None, hand.value(), hand.is_soft()));
+        for ((&i, &down), &open) in playing.iter().zip(&downs).zip(&opens) {
+            let seat = &mut self.seats[i];
+            seat.hand = Hand::new([down, open]);
+            cmds.send_to(seat.pid, BlackjackEvent::DownCard(down));
+            cmds.send(BlackjackEvent::PlayerDraw(seat.pid, open));
+        }
+        cmds.send(BlackjackEvent::DealerDraw(open_dealer));
+        for &i in &playing {
+            self.settle_side_bets(i, open_dealer, cmds.reborrow());
+        }
+        for &i in &playing {
+            let seat = &self.seats[i];
+            cmds.send_to(seat.pid, BlackjackEvent::ValueUpdate(Some(seat.pid), seat.hand.value(), seat.hand.is_soft()));
+        }
+        self.next_turn(0, cmds);
+    }
+    fn handle(&mut self, pid: PlayerId, action: BlackjackAction, mut cmds: Events) {
+        let played = match action {
+            BlackjackAction::Hit => Some(Play::Hit),
+            BlackjackAction::Stand => Some(Play::Stand),
+            BlackjackAction::DoubleDown => Some(Play::DoubleDown),
+            BlackjackAction::Surrender => Some(Play::Surrender),
+            BlackjackAction::Split => Some(Play::Split),
+            BlackjackAction::Bet(_) | BlackjackAction::Hint | BlackjackAction::Count(_) | BlackjackAction::SideBet(..) => None,
+        };
+        if let (Some(played), Some(advice)) = (played, self.advice(pid)) {
+            if played != advice {
+                cmds.log(format!("MISTAKE {pid} {played} {advice}"));
             }
-            _ => (),
+        }
+        match action {
+            BlackjackAction::Bet(bet) => self.bet(pid, bet, cmds),
+            BlackjackAction::Hit => self.hit(pid, cmds),
+            BlackjackAction::Stand => self.stand(pid, cmds),
+            BlackjackAction::DoubleDown => self.double_down(pid, cmds),
+            BlackjackAction::Surrender => self.surrender(pid, cmds),
+            BlackjackAction::Split => self.split(pid, cmds),
+            BlackjackAction::Hint => if let Some(advice) = self.advice(pid) {
+                cmds.send_to(pid, BlackjackEvent::Hint(advice));
+            },
+            BlackjackAction::Count(answer) => self.answer_quiz(pid, answer, cmds),
+            BlackjackAction::SideBet(bet, amount) => self.side_bet(pid, bet, amount, cmds),
+        }
+    }
+    fn turn(&self) -> Option<PlayerId> {
+        if self.game_over {
+            None
+        } else {
+            self.turn.and_then(|i| self.seats.get(i)).map(|seat| seat.pid)
         }
     }
+    fn on_timer(&mut self, pid: PlayerId, cmds: Events) {
+        self.stand(pid, cmds);
+    }
+}
+
+impl Default for Blackjack {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-// TODO: implement multiple hands (and multiple players)
 // TODO: make splits work
 impl Blackjack {
     pub fn new() -> Blackjack {
         Blackjack {
             deck: Deck::empty(),
             dealer_hand: Hand::new([]),
-            player_hand: Hand::new([]),
             dealer: Dealer::h17(),
+            seats: Vec::new(),
             dirty_deck: true,
             game_over: true,
             turn: None,
-            bet: 0,
+            seed: None,
+            fixed_seed: None,
+            trainer: None,
+            pairs_paytable: Some(Paytable::default_for(SideBet::PerfectPairs)),
+            poker_paytable: Some(Paytable::default_for(SideBet::TwentyOnePlusThree)),
 
             due_for_tick: true,
         }
     }
+    fn seat(&self, pid: PlayerId) -> Option<usize> {
+        self.seats.iter().position(|seat| seat.pid == pid)
+    }
+    /// The seat of the player whose move it is, if that's `pid`
+    fn in_turn(&self, pid: PlayerId) -> Option<usize> {
+        self.turn.filter(|&i| !self.game_over && self.seats.get(i).is_some_and(|seat| seat.pid == pid))
+    }
+    /// Hands the turn to the first seat from `from` on that has a move to make, or to the dealer if none has
+    fn next_turn(&mut self, from: usize, mut cmds: Events) {
+        self.turn = None;
+        for (i, seat) in self.seats.iter().enumerate().skip(from) {
+            if !seat.dealt_in() {
+                continue;
+            }
+            if seat.hand.is_natural() {
+                cmds.send_to(seat.pid, BlackjackEvent::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
+                continue;
+            }
+            // Splits aren't dealt yet, so they're never offered
+            let double = seat.stake_with(seat.bet).is_ok();
+            cmds.send_to(seat.pid, BlackjackEvent::Status { hit: true, stand: true, double, surrender: true, split: false, new_game: false });
+            self.turn = Some(i);
+            return;
+        }
+    }
+    /// What basic strategy would play now, if it's `pid`'s turn
+    fn advice(&self, pid: PlayerId) -> Option<Play> {
+        let hand = &self.seats[self.in_turn(pid)?].hand;
+        let &[_, up_card] = self.dealer_hand.cards() else {
+            return None;
+        };
+        let mut options = strategy::Options::for_hand(hand);
+        // Splitting isn't dealt yet
+        options.split = false;
+        Some(strategy::advise(&self.dealer, hand, up_card, options))
+    }
+    /// Counts a card the players got to see
+    fn seen(&mut self, card: Card) {
+        if let Some(trainer) = &mut self.trainer {
+            trainer.count.see(card);
+        }
+    }
+    /// Asks everyone in the round for the count now and again, never right after a shuffle when it'd be a giveaway
+    fn quiz(&mut self, mut cmds: Events) {
+        let Some(trainer) = &mut self.trainer else {
+            return;
+        };
+        if trainer.count.seen == 0 || rand::random::<f64>() >= QUIZ_CHANCE {
+            return;
+        }
+        let true_count = trainer.count.true_count(self.deck.size()).filter(|_| rand::random());
+        let (kind, answer) = match true_count {
+            Some(true_count) => (QuizKind::True, true_count.round() as i32),
+            None => (QuizKind::Running, trainer.count.running),
+        };
+        for seat in self.seats.iter().filter(|s| s.bet > 0) {
+            let quizzed = trainer.players.entry(seat.pid).or_default();
+            if quizzed.quiz.replace((kind, answer)).is_some() {
+                quizzed.asked += 1;
+            }
+            cmds.send_to(seat.pid, BlackjackEvent::Quiz(kind));
+        }
+    }
+    fn answer_quiz(&mut self, pid: PlayerId, guess: i32, mut cmds: Events) {
+        let Some(quizzed) = self.trainer.as_mut().and_then(|t| t.players.get_mut(&pid)) else {
+            return;
+        };
+        let Some((kind, answer)) = quizzed.quiz.take() else {
+            return;
+        };
+        let right = guess == answer;
+        quizzed.asked += 1;
+        quizzed.correct += right as u32;
+        cmds.log(format!("COUNT {pid} {kind} {guess} {answer}"));
+        cmds.send_to(pid, BlackjackEvent::Counted { right, answer, correct: quizzed.correct, asked: quizzed.asked });
+    }
     fn set_due_for_tick(&mut self) {
         self.due_for_tick = true;
     }
@@ -139,76 +793,173 @@ impl Blackjack {
         self.dirty_deck = true;
         self.deck.draw_one().unwrap()
     }
-    fn bet(&mut self, _pn: PlayerId, bet: u32, mut cmds: CommandQueue) {
+    /// Bets go down between rounds, one per seat
+    fn bet(&mut self, pn: PlayerId, bet: u32, mut cmds: Events) {
         self.set_due_for_tick();
-        if self.bet != 0 {
+        if !self.game_over {
+            return;
+        }
+        let Some(i) = self.seat(pn) else {
+            return;
+        };
+        let seat = &mut self.seats[i];
+        if seat.bet != 0 {
+            return;
+        }
+        if bet > MAX_BET {
+            cmds.error(pn, format!("Bets can be at most {MAX_BET}"));
             return;
         }
-        cmds.send(Command::TakeMoney(bet));
-        self.bet = bet;
+        if let Err(e) = seat.put_in(bet) {
+            cmds.error(pn, e.to_owned());
+            return;
+        }
+        cmds.send_to(pn, BlackjackEvent::TakeMoney(bet));
+        seat.bet = bet;
     }
-    fn hit(&mut self, pn: PlayerId, mut cmds: CommandQueue) {
-        self.set_due_for_tick();
-        if self.turn.is_none() || self.game_over || self.bet == 0 {
+    fn paytable(&self, bet: SideBet) -> &Option<Paytable> {
+        match bet {
+            SideBet::PerfectPairs => &self.pairs_paytable,
+            SideBet::TwentyOnePlusThree => &self.poker_paytable,
+        }
+    }
+    fn paytable_mut(&mut self, bet: SideBet) -> &mut Option<Paytable> {
+        match bet {
+            SideBet::PerfectPairs => &mut self.pairs_paytable,
+            SideBet::TwentyOnePlusThree => &mut self.poker_paytable,
+        }
+    }
+    /// Side bets go down between rounds, one of each kind
+    fn side_bet(&mut self, pn: PlayerId, bet: SideBet, amount: u32, mut cmds: Events) {
+        if !self.game_over || amount == 0 || self.paytable(bet).is_none() {
             return;
         }
+        let Some(i) = self.seat(pn) else {
+            return;
+        };
+        let seat = &mut self.seats[i];
+        if seat.side_bets.iter().any(|&(b, _)| b == bet) {
+            return;
+        }
+        if amount > MAX_SIDE_BET {
+            cmds.error(pn, format!("Side bets can be at most {MAX_SIDE_BET}"));
+            return;
+        }
+        if let Err(e) = seat.put_in(amount) {
+            cmds.error(pn, e.to_owned());
+            return;
+        }
+        cmds.send_to(pn, BlackjackEvent::TakeMoney(amount));
+        cmds.send_to(pn, BlackjackEvent::SideBet(bet, amount));
+        seat.side_bets.push((bet, amount));
+    }
+    /// Pays out the side bets on a seat's first two cards and the dealer's up-card
+    fn settle_side_bets(&mut self, i: usize, up_card: Card, mut cmds: Events) {
+        let pid = self.seats[i].pid;
+        let &[first, second] = self.seats[i].hand.cards() else {
+            return;
+        };
+        let mut paid = 0;
+        for (bet, amount) in std::mem::take(&mut self.seats[i].side_bets) {
+            let won = self.paytable(bet).as_ref()
+                .map(|paytable| bet.evaluate([first, second], up_card).map(|hand| (hand, paytable.odds(bet, hand))));
+            let payout = match won {
+                Some(Some((hand, odds))) => {
+                    let payout = amount * (odds + 1);
+                    cmds.send_to(pid, BlackjackEvent::SideWin(bet, hand, payout));
+                    payout
+                }
+                Some(None) => {
+                    cmds.send_to(pid, BlackjackEvent::SideLose(bet));
+                    0
+                }
+                // The host turned the bet off after it was placed, so it's handed back
+                None => amount,
+            };
+            if payout > 0 {
+                cmds.send_to(pid, BlackjackEvent::SendMoney(payout));
+                paid += payout;
+            }
+        }
+        self.seats[i].side_paid += paid;
+    }
+    fn hit(&mut self, pn: PlayerId, mut cmds: Events) {
+        self.set_due_for_tick();
+        let Some(i) = self.in_turn(pn) else {
+            return;
+        };
         let card = self.draw_card();
-        self.player_hand.add_card(card);
-        cmds.send(Command::PlayerDraw(pn, card));
-
-        let value = self.player_hand.value();
-        cmds.send(Command::ValueUpdate(Some(PlayerId::HOST), value, self.player_hand.is_soft()));
-        if self.player_hand.value() > 21 {
-            self.turn = None;
-            cmds.send(Command::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true })
+        self.seen(card);
+        let hand = &mut self.seats[i].hand;
+        hand.add_card(card);
+        cmds.send(BlackjackEvent::PlayerDraw(pn, card));
+
+        cmds.send_to(pn, BlackjackEvent::ValueUpdate(Some(pn), hand.value(), hand.is_soft()));
+        if hand.value() > 21 {
+            cmds.send_to(pn, BlackjackEvent::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
+            self.next_turn(i + 1, cmds);
         } else {
-            cmds.send(Command::Status { hit: true, stand: true, double: true, surrender: false, split: false, new_game: false })
+            // Doubling down is only for the first two cards
+            cmds.send_to(pn, BlackjackEvent::Status { hit: true, stand: true, double: false, surrender: false, split: false, new_game: false })
         }
     }
-    fn stand(&mut self, _pn: PlayerId, mut cmds: CommandQueue) {
+    fn stand(&mut self, pn: PlayerId, mut cmds: Events) {
         self.set_due_for_tick();
-        if self.turn.is_none() || self.game_over || self.bet == 0 {
+        let Some(i) = self.in_turn(pn) else {
             return;
-        }
-        cmds.send(Command::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
-        self.turn = None;
+        };
+        cmds.send_to(pn, BlackjackEvent::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
+        self.next_turn(i + 1, cmds);
     }
-    fn double_down(&mut self, pn: PlayerId, mut cmds: CommandQueue) {
+    fn double_down(&mut self, pn: PlayerId, mut cmds: Events) {
         self.set_due_for_tick();
-        if self.turn.is_none() || self.game_over || self.bet == 0 || self.player_hand.cards().len() > 2 {
+        let Some(i) = self.in_turn(pn).filter(|&i| self.seats[i].hand.cards().len() == 2) else {
+            return;
+        };
+        let seat = &mut self.seats[i];
+        let bet = seat.bet;
+        let Some(doubled) = bet.checked_mul(2) else {
+            return;
+        };
+        if let Err(e) = seat.put_in(bet) {
+            cmds.error(pn, e.to_owned());
             return;
         }
+        seat.bet = doubled;
+
         let card = self.draw_card();
-        self.player_hand.add_card(card);
-        cmds.send(Command::PlayerDraw(pn, card));
+        self.seen(card);
+        let seat = &mut self.seats[i];
+        seat.hand.add_card(card);
+        cmds.send(BlackjackEvent::PlayerDraw(pn, card));
 
-        cmds.send(Command::TakeMoney(self.bet));
-        self.bet += self.bet;
+        cmds.send_to(pn, BlackjackEvent::TakeMoney(bet));
 
-        let value = self.player_hand.value();
-        cmds.send(Command::ValueUpdate(Some(PlayerId::HOST), value, self.player_hand.is_soft()));
-        cmds.send(Command::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
-        self.turn = None;
+        cmds.send_to(pn, BlackjackEvent::ValueUpdate(Some(pn), seat.hand.value(), seat.hand.is_soft()));
+        cmds.send_to(pn, BlackjackEvent::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
+        self.next_turn(i + 1, cmds);
     }
-    fn surrender(&mut self, _pn: PlayerId, mut cmds: CommandQueue) {
+    fn surrender(&mut self, pn: PlayerId, mut cmds: Events) {
         self.set_due_for_tick();
-        if self.turn.is_none() || self.game_over || self.bet == 0 || self.player_hand.cards().len() > 2 {
+        let Some(i) = self.in_turn(pn).filter(|&i| self.seats[i].hand.cards().len() == 2) else {
             return;
-        }
+        };
 
-        let give_back = self.bet / 2;
-        self.bet = 0;
-        cmds.send(Command::SendMoney(give_back));
+        let seat = &mut self.seats[i];
+        let give_back = seat.bet / 2;
+        seat.bet = 0;
+        seat.refunded += give_back;
+        cmds.send_to(pn, BlackjackEvent::SendMoney(give_back));
 
-        cmds.send(Command::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
-        self.turn = None;
+        cmds.send_to(pn, BlackjackEvent::Status { hit: false, stand: false, double: false, surrender: false, split: false, new_game: true });
+        self.next_turn(i + 1, cmds);
     }
-    fn split(&mut self, _pn: PlayerId, _cmds: CommandQueue) {
+    fn split(&mut self, pn: PlayerId, _cmds: Events) {
         self.set_due_for_tick();
-        if self.turn.is_none() || self.game_over || self.bet == 0 {
+        let Some(i) = self.in_turn(pn) else {
             return;
-        }
-        match self.player_hand.cards() {
+        };
+        match self.seats[i].hand.cards() {
             &[c1, c2] if c1.suit_rank().1 == c2.suit_rank().1 => (),
             _ => return,
         }
@@ -0,0 +1,95 @@
+use crate::card::Card;
+use crate::dealer::Dealer;
+use crate::fellestrekk::{Bot, Command, Difficulty, PlayerId};
+use crate::games::GameId;
+use crate::hand::Hand;
+use crate::strategy::{self, Options, Play};
+
+use super::{BlackjackAction, BlackjackEvent};
+
+/// What the bot bets every round
+const BET: u32 = 100;
+/// How often a bot on normal difficulty forgets basic strategy and plays like the dealer
+const SLIP_CHANCE: f64 = 0.1;
+
+/// Plays Blackjack by basic strategy, the easier the worse
+///
+/// On easy it plays by the dealer's rules, on normal it slips up every so often and on hard it never does.
+pub struct BlackjackBot {
+    difficulty: Difficulty,
+    /// Taken to hit soft 17, the bot only hears about the rules when it joins or they change
+    dealer: Dealer,
+    hand: Hand,
+    up_card: Option<Card>,
+    /// Whether the roster has shown the bot in its seat yet
+    seated: bool,
+}
+
+impl BlackjackBot {
+    pub fn new(difficulty: Difficulty) -> Self {
+        BlackjackBot { difficulty, dealer: Dealer::h17(), hand: Hand::default(), up_card: None, seated: false }
+    }
+    /// The bot's move, if it has seen the dealer's up-card to go by
+    fn play(&self, options: Options) -> Option<Play> {
+        let up_card = self.up_card?;
+        let slip = match self.difficulty {
+            Difficulty::Easy => true,
+            Difficulty::Normal => rand::random::<f64>() < SLIP_CHANCE,
+            Difficulty::Hard => false,
+        };
+        Some(if slip {
+            if self.dealer.hits(&self.hand) { Play::Hit } else { Play::Stand }
+        } else {
+            strategy::advise(&self.dealer, &self.hand, up_card, options)
+        })
+    }
+    fn event(&mut self, pid: PlayerId, event: BlackjackEvent) -> Option<BlackjackAction> {
+        match event {
+            BlackjackEvent::Start => {
+                self.hand = Hand::default();
+                self.up_card = None;
+            }
+            BlackjackEvent::DownCard(card) => self.hand.add_card(card),
+            BlackjackEvent::PlayerDraw(p, card) if p == pid => self.hand.add_card(card),
+            BlackjackEvent::DealerDraw(card) if self.up_card.is_none() => self.up_card = Some(card),
+            BlackjackEvent::Status { new_game: true, .. } => (),
+            BlackjackEvent::Status { hit, stand, double, surrender, split: _, new_game: false } if hit || stand => {
+                let allowed = Options::for_hand(&self.hand);
+                let options = Options {
+                    double: double && allowed.double,
+                    surrender: surrender && allowed.surrender,
+                    // Splitting isn't dealt yet
+                    split: false,
+                };
+                return Some(match self.play(options)? {
+                    Play::Hit => BlackjackAction::Hit,
+                    Play::DoubleDown => BlackjackAction::DoubleDown,
+                    Play::Surrender => BlackjackAction::Surrender,
+                    Play::Stand | Play::Split => BlackjackAction::Stand,
+                });
+            }
+            _ => (),
+        }
+        None
+    }
+}
+
+impl Bot for BlackjackBot {
+    fn receive(&mut self, pid: PlayerId, cmd: &Command) -> Vec<Command> {
+        let action = match cmd {
+            // Its bet is down as soon as it sits and again after every round
+            Command::Players(players) if !self.seated && players.iter().any(|&(p, _)| p == pid) => {
+                self.seated = true;
+                Some(BlackjackAction::Bet(BET))
+            }
+            Command::RoundOver(_) => Some(BlackjackAction::Bet(BET)),
+            Command::Setting(setting) if setting.key == "dealer" => {
+                self.dealer = if setting.value == "S17" { Dealer::s17() } else { Dealer::h17() };
+                None
+            }
+            Command::Game(GameId::Blackjack, payload) => payload.parse().ok().and_then(|event| self.event(pid, event)),
+            _ => None,
+        };
+        action.map(|action| Command::Game(GameId::Blackjack, action.to_string())).into_iter().collect()
+    }
+}
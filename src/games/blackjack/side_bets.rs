@@ -0,0 +1,250 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::card::{Card, Rank, Suit};
+
+/// A bet on the first cards dealt, placed next to the main bet and settled right after the deal
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SideBet {
+    /// On the player's two cards being a pair
+    PerfectPairs,
+    /// On the player's two cards and the dealer's up-card making a three card poker hand
+    TwentyOnePlusThree,
+}
+
+/// A hand that wins a side bet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SideHand {
+    PerfectPair,
+    ColouredPair,
+    MixedPair,
+    SuitedTrips,
+    StraightFlush,
+    ThreeOfAKind,
+    Straight,
+    Flush,
+}
+
+impl SideBet {
+    pub const ALL: [SideBet; 2] = [SideBet::PerfectPairs, SideBet::TwentyOnePlusThree];
+
+    /// The setting its paytable is chosen with
+    pub fn key(self) -> &'static str {
+        match self {
+            SideBet::PerfectPairs => "pairs",
+            SideBet::TwentyOnePlusThree => "21+3",
+        }
+    }
+    /// The paytables the host can choose from, the first one being the default
+    pub fn paytables(self) -> &'static [&'static str] {
+        match self {
+            SideBet::PerfectPairs => &["25/12/6", "30/10/5"],
+            SideBet::TwentyOnePlusThree => &["100/40/30/10/5", "9"],
+        }
+    }
+    /// The hands that win, best first, in the order their odds are given in a paytable
+    fn hands(self) -> &'static [SideHand] {
+        use SideHand::*;
+        match self {
+            SideBet::PerfectPairs => &[PerfectPair, ColouredPair, MixedPair],
+            SideBet::TwentyOnePlusThree => &[SuitedTrips, StraightFlush, ThreeOfAKind, Straight, Flush],
+        }
+    }
+    /// The winning hand, if the cards make one
+    pub fn evaluate(self, player: [Card; 2], up_card: Card) -> Option<SideHand> {
+        match self {
+            SideBet::PerfectPairs => pair(player),
+            SideBet::TwentyOnePlusThree => poker([player[0], player[1], up_card]),
+        }
+    }
+}
+
+impl Display for SideBet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SideBet::PerfectPairs => write!(f, "PAIRS"),
+            SideBet::TwentyOnePlusThree => write!(f, "21+3"),
+        }
+    }
+}
+
+impl FromStr for SideBet {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PAIRS" => Ok(SideBet::PerfectPairs),
+            "21+3" => Ok(SideBet::TwentyOnePlusThree),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for SideHand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SideHand::PerfectPair => write!(f, "PERFECTPAIR"),
+            SideHand::ColouredPair => write!(f, "COLOUREDPAIR"),
+            SideHand::MixedPair => write!(f, "MIXEDPAIR"),
+            SideHand::SuitedTrips => write!(f, "SUITEDTRIPS"),
+            SideHand::StraightFlush => write!(f, "STRAIGHTFLUSH"),
+            SideHand::ThreeOfAKind => write!(f, "THREEOFAKIND"),
+            SideHand::Straight => write!(f, "STRAIGHT"),
+            SideHand::Flush => write!(f, "FLUSH"),
+        }
+    }
+}
+
+impl FromStr for SideHand {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PERFECTPAIR" => Ok(SideHand::PerfectPair),
+            "COLOUREDPAIR" => Ok(SideHand::ColouredPair),
+            "MIXEDPAIR" => Ok(SideHand::MixedPair),
+            "SUITEDTRIPS" => Ok(SideHand::SuitedTrips),
+            "STRAIGHTFLUSH" => Ok(SideHand::StraightFlush),
+            "THREEOFAKIND" => Ok(SideHand::ThreeOfAKind),
+            "STRAIGHT" => Ok(SideHand::Straight),
+            "FLUSH" => Ok(SideHand::Flush),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The most a winning hand can pay to one
+pub const MAX_ODDS: u32 = 1000;
+
+/// What each winning hand pays to one, written like `25/12/6`
+///
+/// A single number pays the same for every winning hand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Paytable(Vec<u32>);
+
+impl Paytable {
+    pub fn default_for(bet: SideBet) -> Self {
+        bet.paytables()[0].parse().expect("valid default paytable")
+    }
+    pub fn odds(&self, bet: SideBet, hand: SideHand) -> u32 {
+        match &*self.0 {
+            &[odds] => odds,
+            odds => bet.hands().iter().position(|&h| h == hand).and_then(|i| odds.get(i).copied()).unwrap_or(0),
+        }
+    }
+}
+
+impl Display for Paytable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, odds) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{odds}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Paytable {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split('/')
+            .map(|odds| odds.parse().ok().filter(|&odds| odds <= MAX_ODDS).ok_or(()))
+            .collect::<Result<_, _>>()
+            .map(Paytable)
+    }
+}
+
+fn is_red(suit: Suit) -> bool {
+    matches!(suit, Suit::Hearts | Suit::Diamonds)
+}
+
+fn pair([a, b]: [Card; 2]) -> Option<SideHand> {
+    let ((suit_a, rank_a), (suit_b, rank_b)) = (a.suit_rank(), b.suit_rank());
+    if rank_a != rank_b {
+        None
+    } else if suit_a == suit_b {
+        Some(SideHand::PerfectPair)
+    } else if is_red(suit_a) == is_red(suit_b) {
+        Some(SideHand::ColouredPair)
+    } else {
+        Some(SideHand::MixedPair)
+    }
+}
+
+fn poker(cards: [Card; 3]) -> Option<SideHand> {
+    let suits = cards.map(|c| c.suit_rank().0);
+    let mut ranks = cards.map(|c| c.suit_rank().1 as u8);
+    ranks.sort_unstable();
+
+    let flush = suits[0] == suits[1] && suits[1] == suits[2];
+    let trips = ranks[0] == ranks[1] && ranks[1] == ranks[2];
+    let ace = Rank::Ace as u8;
+    // Aces go low or high, so queen, king, ace is a straight too
+    let straight = (ranks[1] == ranks[0] + 1 && ranks[2] == ranks[1] + 1)
+        || ranks == [ace, Rank::Queen as u8, Rank::King as u8];
+
+    match (trips, straight, flush) {
+        (true, _, true) => Some(SideHand::SuitedTrips),
+        (_, true, true) => Some(SideHand::StraightFlush),
+        (true, _, _) => Some(SideHand::ThreeOfAKind),
+        (_, true, _) => Some(SideHand::Straight),
+        (_, _, true) => Some(SideHand::Flush),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn three_card(player: [&str; 2], up_card: &str) -> Option<SideHand> {
+        SideBet::TwentyOnePlusThree.evaluate(player.map(|c| c.parse().unwrap()), up_card.parse().unwrap())
+    }
+
+    #[test]
+    fn aces_make_straights_low_and_high() {
+        assert_eq!(three_card(["♠A", "♥2"], "♦3"), Some(SideHand::Straight));
+        assert_eq!(three_card(["♠Q", "♥K"], "♦A"), Some(SideHand::Straight));
+        assert_eq!(three_card(["♠K", "♥A"], "♦2"), None);
+    }
+
+    #[test]
+    fn suited_trips_beat_a_straight_flush() {
+        assert_eq!(three_card(["♠7", "♠7"], "♠7"), Some(SideHand::SuitedTrips));
+        assert_eq!(three_card(["♠7", "♥7"], "♠7"), Some(SideHand::ThreeOfAKind));
+        assert_eq!(three_card(["♠5", "♠6"], "♠7"), Some(SideHand::StraightFlush));
+        assert_eq!(three_card(["♠5", "♠9"], "♠7"), Some(SideHand::Flush));
+    }
+
+    #[test]
+    fn pairs_by_colour() {
+        let pair = |a: &str, b: &str| SideBet::PerfectPairs.evaluate([a.parse().unwrap(), b.parse().unwrap()], "♣2".parse().unwrap());
+        assert_eq!(pair("♠9", "♠9"), Some(SideHand::PerfectPair));
+        assert_eq!(pair("♠9", "♣9"), Some(SideHand::ColouredPair));
+        assert_eq!(pair("♠9", "♥9"), Some(SideHand::MixedPair));
+        assert_eq!(pair("♠9", "♠10"), None);
+    }
+
+    #[test]
+    fn single_number_paytable_pays_every_hand_the_same() {
+        let table: Paytable = "9".parse().unwrap();
+        assert_eq!(table.odds(SideBet::TwentyOnePlusThree, SideHand::SuitedTrips), 9);
+        assert_eq!(table.odds(SideBet::TwentyOnePlusThree, SideHand::Flush), 9);
+        assert_eq!(table.to_string(), "9");
+    }
+
+    #[test]
+    fn paytable_odds_follow_the_order_of_the_hands() {
+        let table = Paytable::default_for(SideBet::PerfectPairs);
+        assert_eq!(table.odds(SideBet::PerfectPairs, SideHand::PerfectPair), 25);
+        assert_eq!(table.odds(SideBet::PerfectPairs, SideHand::MixedPair), 6);
+        assert_eq!(table.odds(SideBet::PerfectPairs, SideHand::Flush), 0);
+    }
+
+    #[test]
+    fn paytables_pay_at_most_the_limit() {
+        assert!(format!("25/{MAX_ODDS}").parse::<Paytable>().is_ok());
+        assert!(format!("25/{}", MAX_ODDS + 1).parse::<Paytable>().is_err());
+        assert!("4294967295".parse::<Paytable>().is_err());
+    }
+}
@@ -2,7 +2,7 @@ use std::cmp::Ordering;
 
 use crate::card::Card;
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Hand {
     cards: Vec<Card>,
     soft: bool,
@@ -96,4 +96,64 @@ impl BlackjackExt for Hand {
     fn is_natural(&self) -> bool {
         self.cards().len() == 2 && self.value() == 21
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hand(cards: &[&str]) -> Hand {
+        let mut hand = Hand::default();
+        for card in cards {
+            hand.add_card(card.parse().unwrap());
+        }
+        hand
+    }
+
+    #[test]
+    fn face_cards_count_ten() {
+        let hand = hand(&["♠K", "♥Q", "♦J"]);
+        assert_eq!(hand.value(), 30);
+        assert!(!hand.is_soft());
+        assert!(hand.is_bust());
+    }
+
+    #[test]
+    fn aces_count_eleven_unless_that_busts() {
+        let soft = hand(&["♠A", "♥6"]);
+        assert_eq!(soft.value(), 17);
+        assert!(soft.is_soft());
+
+        let mut hard = soft.clone();
+        hard.add_card("♣9".parse().unwrap());
+        assert_eq!(hard.value(), 16);
+        assert!(!hard.is_soft());
+
+        let two_aces = hand(&["♠A", "♥A"]);
+        assert_eq!(two_aces.value(), 12);
+        assert!(two_aces.is_soft());
+    }
+
+    #[test]
+    fn only_two_cards_make_a_natural() {
+        assert!(hand(&["♠A", "♥K"]).is_natural());
+        assert!(!hand(&["♠7", "♥7", "♦7"]).is_natural());
+        assert_eq!(hand(&["♠7", "♥7", "♦7"]).value(), 21);
+    }
+
+    #[test]
+    fn comparing_hands() {
+        let natural = hand(&["♠A", "♥K"]);
+        let three_card_21 = hand(&["♠7", "♥7", "♦7"]);
+        let twenty = hand(&["♠K", "♥Q"]);
+        let bust = hand(&["♠K", "♥Q", "♦2"]);
+
+        assert_eq!(natural.cmp(&three_card_21), Ordering::Greater);
+        assert_eq!(three_card_21.cmp(&natural), Ordering::Less);
+        assert_eq!(natural.cmp(&natural), Ordering::Equal);
+        assert_eq!(three_card_21.cmp(&twenty), Ordering::Greater);
+        assert_eq!(twenty.cmp(&twenty), Ordering::Equal);
+        // A busted player loses even to a busted dealer
+        assert_eq!(bust.cmp(&bust), Ordering::Less);
+        assert_eq!(twenty.cmp(&bust), Ordering::Greater);
+    }
+}
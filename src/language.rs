@@ -148,12 +148,38 @@ pub struct Language {
     pub or_join: Box<str>,
     pub game_code: Box<str>,
     pub join: Box<str>,
+    pub your_name: Box<str>,
 
     pub not_found_title: Box<str>,
     pub the_page_was_not_found: Box<str>,
 
     pub game_title: Box<str>,
     pub write_to_opponent_here: Box<str>,
+    pub players: Box<str>,
+    pub scoreboard: Box<str>,
+
+    pub lobby_title: Box<str>,
+    pub find_game: Box<str>,
+    pub no_public_games: Box<str>,
+    pub lobby_game: Box<str>,
+    pub lobby_host: Box<str>,
+    pub lobby_rules: Box<str>,
+    pub lobby_seats: Box<str>,
+    pub lobby_spectators: Box<str>,
+    pub spectate: Box<str>,
+    pub quick_match: Box<str>,
+
+    pub admin_title: Box<str>,
+    pub admin_token: Box<str>,
+    pub admin_wrong_token: Box<str>,
+    pub admin_notice: Box<str>,
+    pub admin_send: Box<str>,
+    pub admin_age: Box<str>,
+    pub admin_idle: Box<str>,
+    pub admin_inspect: Box<str>,
+    pub admin_close: Box<str>,
+    pub admin_close_reason: Box<str>,
+    pub admin_no_sessions: Box<str>,
 
     pub game: Game,
 }
@@ -167,11 +193,47 @@ pub struct Game {
     join_success: Box<str>,
     your_turn: Box<str>,
     opponents_turn: Box<str>,
-    unknown: Box<str>
+    unknown: Box<str>,
+    player: Box<str>,
+    spectator: Box<str>,
+    spectating: Box<str>,
+    spectators_allowed: Box<str>,
+    spectate_instead: Box<str>,
+    enter_password: Box<str>,
+    was_muted: Box<str>,
+    was_unmuted: Box<str>,
+    locked: Box<str>,
+    unlocked: Box<str>,
+    joined: Box<str>,
+    left: Box<str>,
+    kicked: Box<str>,
+    won: Box<str>,
+    lost: Box<str>,
+    tied: Box<str>,
+    turn_time: Box<str>,
+    setting: Box<str>,
+    finished: Box<str>,
+    round_over: Box<str>,
+    replay: Box<str>,
+    no_replay: Box<str>,
+    score_hands: Box<str>,
+    score_record: Box<str>,
+    score_net: Box<str>,
+    account_registered: Box<str>,
+    account_logged_in: Box<str>,
+    account_logged_out: Box<str>,
+    account_nickname: Box<str>,
+    now_public: Box<str>,
+    now_private: Box<str>,
+    queued: Box<str>,
+    hint: Box<str>,
+    mistake: Box<str>,
+    quiz_running: Box<str>,
+    quiz_true: Box<str>,
+    count_right: Box<str>,
+    count_wrong: Box<str>,
+    count_score: Box<str>,
+    side_bet_placed: Box<str>,
+    side_bet_won: Box<str>,
+    side_bet_lost: Box<str>,
 }
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct TrueFalse {
-    r#true: String,
-    r#false: String,
-}
\ No newline at end of file
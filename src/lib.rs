@@ -0,0 +1,9 @@
+//! The cards and Blackjack rules, shared by the server and the tools in `src/bin`
+
+#[macro_use] extern crate serde_derive;
+
+pub mod card;
+pub mod counting;
+pub mod dealer;
+pub mod hand;
+pub mod strategy;
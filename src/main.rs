@@ -2,8 +2,8 @@
 #[macro_use] extern crate serde_derive;
 
 use rocket::{
-    http::Status,
-    response::Redirect,
+    http::{Cookie, CookieJar, SameSite, Status},
+    response::{Redirect, status::BadRequest},
     outcome::try_outcome,
     fs::{NamedFile, FileServer},
     request::{FromRequest, Outcome},
@@ -12,12 +12,15 @@ use rocket::{
 };
 use rocket_dyn_templates::Template;
 
-pub mod card;
-pub mod dealer;
-pub mod hand;
 pub mod games;
 
+use ::fellestrekk::{card, counting, dealer, hand, strategy};
+
+mod accounts;
 mod language;
+mod storage;
+
+use accounts::{Account, AccountInfo};
 
 use language::{new_shared_language_cache, SharedLanguageCache, LangIcon, Language, Game as GameStrings};
 
@@ -60,6 +63,109 @@ fn index(lt: LangTemplate) -> Template {
 fn game(lt: LangTemplate, code: Option<&str>) -> Template {
     Template::render("game", &lt)
 }
+#[get("/lobby")]
+fn lobby(lt: LangTemplate) -> Template {
+    Template::render("lobby", &lt)
+}
+/// The page itself is public, what it shows is fetched from the admin routes with the token typed in
+#[get("/admin")]
+fn admin(lt: LangTemplate) -> Template {
+    Template::render("admin", &lt)
+}
+/// The public sessions, `/lobby/ws` sends the same list again whenever it changes
+#[get("/api/games")]
+fn api_games(games: &State<SessionStore>) -> Json<Vec<Listing>> {
+    Json(games.lobby.games())
+}
+#[derive(Serialize)]
+struct ReplayTemplate {
+    #[serde(flatten)]
+    lt: LangTemplate,
+    replay: Replay,
+}
+#[derive(Serialize)]
+struct Replay {
+    code: String,
+    round: u32,
+}
+#[get("/replay/<code>/<round>")]
+fn replay(lt: LangTemplate, code: &str, round: u32) -> Option<Template> {
+    u16::from_str_radix(code, 16).ok()?;
+    let replay = Replay { code: code.to_uppercase(), round };
+    Some(Template::render("game", ReplayTemplate { lt, replay }))
+}
+#[get("/history/<code>/<round>")]
+async fn history(code: &str, round: u32, games: &State<SessionStore>) -> Option<Json<Round>> {
+    let code = u16::from_str_radix(code, 16).ok()?;
+    games.history(code, round).await.map(Json)
+}
+#[get("/stats")]
+async fn stats(games: &State<SessionStore>) -> Json<Vec<Standing>> {
+    Json(games.leaderboard().await)
+}
+#[get("/stats/<name>")]
+async fn player_stats(name: &str, games: &State<SessionStore>) -> Option<Json<PlayerStats>> {
+    games.player_stats(name).await.map(Json)
+}
+#[derive(Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+#[derive(Deserialize)]
+struct Nickname {
+    nickname: String,
+}
+async fn current_account(cookies: &CookieJar<'_>, games: &SessionStore) -> Option<Account> {
+    games.accounts.get(cookies.get(accounts::COOKIE)?.value()).await
+}
+/// The token is all it takes to play as the account, so scripts and other sites never get to see it
+fn log_in(cookies: &CookieJar<'_>, account: &Account) -> Json<AccountInfo> {
+    let cookie = Cookie::build((accounts::COOKIE, account.token().to_owned()))
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .permanent();
+    cookies.add(cookie);
+    Json(account.info())
+}
+#[get("/account")]
+async fn account(cookies: &CookieJar<'_>, games: &State<SessionStore>) -> Option<Json<AccountInfo>> {
+    current_account(cookies, games).await.map(|a| Json(a.info()))
+}
+/// Gives the browser an anonymous account, unless it already has one
+#[post("/account")]
+async fn new_account(cookies: &CookieJar<'_>, games: &State<SessionStore>) -> Json<AccountInfo> {
+    let account = match current_account(cookies, games).await {
+        Some(account) => account,
+        None => games.accounts.create().await,
+    };
+    log_in(cookies, &account)
+}
+/// Registers the browser's anonymous account, so it keeps its wallet and statistics
+#[post("/account/register", data = "<credentials>")]
+async fn register(credentials: Json<Credentials>, cookies: &CookieJar<'_>, games: &State<SessionStore>) -> Result<Json<AccountInfo>, BadRequest<String>> {
+    let token = cookies.get(accounts::COOKIE).map(|c| c.value().to_owned());
+    let account = games.accounts.register(token.as_deref(), &credentials.username, &credentials.password).await
+        .map_err(|e| BadRequest(e.to_string()))?;
+    Ok(log_in(cookies, &account))
+}
+#[post("/account/login", data = "<credentials>")]
+async fn login(credentials: Json<Credentials>, cookies: &CookieJar<'_>, games: &State<SessionStore>) -> Result<Json<AccountInfo>, BadRequest<String>> {
+    let account = games.accounts.login(&credentials.username, &credentials.password).await
+        .map_err(|e| BadRequest(e.to_string()))?;
+    Ok(log_in(cookies, &account))
+}
+#[post("/account/logout")]
+fn logout(cookies: &CookieJar<'_>) {
+    cookies.remove(accounts::COOKIE);
+}
+#[post("/account/nickname", data = "<nickname>")]
+async fn nickname(nickname: Json<Nickname>, cookies: &CookieJar<'_>, games: &State<SessionStore>) -> Result<Json<AccountInfo>, BadRequest<String>> {
+    let token = cookies.get(accounts::COOKIE).map(|c| c.value().to_owned()).unwrap_or_default();
+    let account = games.accounts.set_nickname(&token, &nickname.nickname).await
+        .map_err(|e| BadRequest(e.to_string()))?;
+    Ok(Json(account.info()))
+}
 #[get("/strings/<code>")]
 fn strings(code: &str, slc: &State<SharedLanguageCache>) -> Option<Json<GameStrings>> {
     lang(code, slc).map(|l| Json(l.0.game))
@@ -71,7 +177,7 @@ fn lang(code: &str, slc: &State<SharedLanguageCache>) -> Option<Json<Language>>
         dbg!(code);
         return None;
     }
-    slc.lock().unwrap().get(&code[..dot]).map(|l| Json(l))
+    slc.lock().unwrap().get(&code[..dot]).map(Json)
 }
 
 #[get("/favicon.ico")]
@@ -142,6 +248,25 @@ fn rocket() -> rocket::Rocket<Build> {
                 ip,
                 ip_json,
                 robots,
+                replay,
+                history,
+                stats,
+                player_stats,
+                account,
+                new_account,
+                register,
+                login,
+                logout,
+                nickname,
+                lobby,
+                api_games,
+                admin,
+                fellestrekk::admin_sessions,
+                fellestrekk::admin_session,
+                fellestrekk::admin_notice,
+                fellestrekk::admin_close,
+                fellestrekk::prometheus,
+                fellestrekk::lobby_ws,
                 fellestrekk::ws,
             ],
         )
@@ -152,12 +277,21 @@ fn rocket() -> rocket::Rocket<Build> {
 
 mod fellestrekk;
 
-use fellestrekk::SessionStore;
+use fellestrekk::{AdminToken, ChatFilter, Listing, PlayerStats, Round, SessionStore, Standing};
+use storage::Storage;
 
 #[rocket::launch]
-fn rocket_launch() -> _ {
-    let games = SessionStore::new();
+async fn rocket_launch() -> _ {
+    let rocket = rocket();
+    let chat_filter = rocket.figment().extract_inner("chat_filter").unwrap_or_default();
+    let storage = rocket.figment().extract_inner::<std::path::PathBuf>("storage_dir").ok().map(Storage::new);
+    let deck_seed = rocket.figment().extract_inner("deck_seed").ok();
+    let games = SessionStore::new(ChatFilter::new(chat_filter), storage, deck_seed);
+    games.restore().await;
+
+    let admin_token = AdminToken::new(rocket.figment().extract_inner("admin_token").ok());
 
-    rocket()
+    rocket
         .manage(games)
+        .manage(admin_token)
 }
@@ -0,0 +1,67 @@
+use rocket::tokio::fs;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use std::io;
+use std::path::PathBuf;
+
+/// Keeps things around between restarts as JSON files, configured with `storage_dir` in `Rocket.toml`
+///
+/// Every kind of thing gets its own subdirectory, with a file per key.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    dir: PathBuf,
+}
+
+impl Storage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Storage { dir: dir.into() }
+    }
+    fn path(&self, kind: &str, key: &str) -> PathBuf {
+        self.dir.join(kind).join(format!("{key}.json"))
+    }
+    /// Writes to a temporary file first, so a crash never leaves half a file behind
+    pub async fn save<T: Serialize>(&self, kind: &str, key: &str, value: &T) -> io::Result<()> {
+        let json = serde_json::to_vec(value)?;
+        let path = self.path(kind, key);
+        let tmp = path.with_extension("json.tmp");
+        fs::create_dir_all(self.dir.join(kind)).await?;
+        fs::write(&tmp, json).await?;
+        fs::rename(tmp, path).await
+    }
+    pub async fn load<T: DeserializeOwned>(&self, kind: &str, key: &str) -> io::Result<Option<T>> {
+        match fs::read(self.path(kind, key)).await {
+            Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    pub async fn remove(&self, kind: &str, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(kind, key)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+    /// Everything of a kind that could be read, files that fail to parse are skipped
+    pub async fn load_all<T: DeserializeOwned>(&self, kind: &str) -> io::Result<Vec<(String, T)>> {
+        let mut entries = match fs::read_dir(self.dir.join(kind)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut all = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(key) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".json")) else {
+                continue;
+            };
+            let key = key.to_owned();
+            match serde_json::from_slice(&fs::read(&path).await?) {
+                Ok(value) => all.push((key, value)),
+                Err(e) => eprintln!("Skipping {}: {e}", path.display()),
+            }
+        }
+        Ok(all)
+    }
+}
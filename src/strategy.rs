@@ -0,0 +1,206 @@
+//! Blackjack basic strategy
+//!
+//! The play with the best expected value for every hand against every dealer up-card,
+//! from the usual multi-deck charts (doubling after splits allowed, late surrender) for either dealer rule.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::card::{Card, Rank};
+use crate::dealer::Dealer;
+use crate::hand::Hand;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Play {
+    Hit,
+    Stand,
+    DoubleDown,
+    Split,
+    Surrender,
+}
+
+/// Sent the same way as the player's actions
+impl Display for Play {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Play::Hit => write!(f, "HIT"),
+            Play::Stand => write!(f, "STAND"),
+            Play::DoubleDown => write!(f, "DOUBLEDOWN"),
+            Play::Split => write!(f, "SPLIT"),
+            Play::Surrender => write!(f, "SURRENDER"),
+        }
+    }
+}
+
+impl FromStr for Play {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HIT" => Ok(Play::Hit),
+            "STAND" => Ok(Play::Stand),
+            "DOUBLEDOWN" => Ok(Play::DoubleDown),
+            "SPLIT" => Ok(Play::Split),
+            "SURRENDER" => Ok(Play::Surrender),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Which plays the table allows for the hand right now
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub double: bool,
+    pub split: bool,
+    pub surrender: bool,
+}
+
+impl Options {
+    /// Everything allowed on a two card hand, only hitting and standing after that
+    pub fn for_hand(hand: &Hand) -> Self {
+        let first_move = hand.cards().len() == 2;
+        Options { double: first_move, split: first_move, surrender: first_move }
+    }
+}
+
+/// What the card counts for in Blackjack, aces as 11
+pub fn card_value(card: Card) -> u8 {
+    match card.suit_rank().1 {
+        Rank::Ace => 11,
+        Rank::King | Rank::Queen | Rank::Jack | Rank::Ten => 10,
+        rank => rank as u8 + 1,
+    }
+}
+
+/// The basic strategy play for the hand against the dealer's up-card
+pub fn advise(dealer: &Dealer, hand: &Hand, up_card: Card, options: Options) -> Play {
+    let up = card_value(up_card);
+    let h17 = dealer.hits_soft_17();
+    let value = hand.value();
+
+    if options.surrender {
+        if let Some(play) = surrender(h17, hand, up) {
+            return play;
+        }
+    }
+    if options.split {
+        if let &[a, b] = hand.cards() {
+            if card_value(a) == card_value(b) && split(card_value(a), up) {
+                return Play::Split;
+            }
+        }
+    }
+    if hand.is_soft() {
+        soft(h17, value, up, options.double)
+    } else {
+        hard(h17, value, up, options.double)
+    }
+}
+
+fn surrender(h17: bool, hand: &Hand, up: u8) -> Option<Play> {
+    if hand.is_soft() {
+        return None;
+    }
+    let pair_of_eights = matches!(hand.cards(), &[a, b] if card_value(a) == 8 && card_value(b) == 8);
+    let surrender = match (hand.value(), up) {
+        (16, 9..=11) if !pair_of_eights => true,
+        (15, 10) => true,
+        (15, 11) | (17, 11) => h17,
+        (16, 11) => h17,
+        _ => false,
+    };
+    surrender.then_some(Play::Surrender)
+}
+
+fn split(pair: u8, up: u8) -> bool {
+    match pair {
+        11 | 8 => true,
+        9 => matches!(up, 2..=6 | 8 | 9),
+        7 | 3 | 2 => up <= 7,
+        6 => up <= 6,
+        4 => matches!(up, 5 | 6),
+        // Tens and fives are played as the total they make
+        _ => false,
+    }
+}
+
+fn soft(h17: bool, value: u8, up: u8, can_double: bool) -> Play {
+    let double = |yes: bool, otherwise: Play| if yes && can_double { Play::DoubleDown } else { otherwise };
+    match value {
+        20.. => Play::Stand,
+        19 => double(h17 && up == 6, Play::Stand),
+        18 => match up {
+            2 => double(h17, Play::Stand),
+            3..=6 => double(true, Play::Stand),
+            7 | 8 => Play::Stand,
+            _ => Play::Hit,
+        },
+        17 => double(matches!(up, 3..=6), Play::Hit),
+        15 | 16 => double(matches!(up, 4..=6), Play::Hit),
+        _ => double(matches!(up, 5 | 6), Play::Hit),
+    }
+}
+
+fn hard(h17: bool, value: u8, up: u8, can_double: bool) -> Play {
+    let double = |yes: bool| if yes && can_double { Play::DoubleDown } else { Play::Hit };
+    match value {
+        17.. => Play::Stand,
+        13..=16 => if up <= 6 { Play::Stand } else { Play::Hit },
+        12 => if matches!(up, 4..=6) { Play::Stand } else { Play::Hit },
+        11 => double(up != 11 || h17),
+        10 => double(up <= 9),
+        9 => double(matches!(up, 3..=6)),
+        _ => Play::Hit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hand(cards: &[&str]) -> Hand {
+        let mut hand = Hand::default();
+        for card in cards {
+            hand.add_card(card.parse().unwrap());
+        }
+        hand
+    }
+
+    fn advise_both(cards: &[&str], up_card: &str) -> (Play, Play) {
+        let hand = hand(cards);
+        let up_card = up_card.parse().unwrap();
+        let options = Options::for_hand(&hand);
+        (advise(&Dealer::h17(), &hand, up_card, options), advise(&Dealer::s17(), &hand, up_card, options))
+    }
+
+    #[test]
+    fn soft_18_against_a_two_doubles_only_when_the_dealer_hits_soft_17() {
+        assert_eq!(advise_both(&["♠A", "♥7"], "♣2"), (Play::DoubleDown, Play::Stand));
+    }
+
+    #[test]
+    fn eleven_against_an_ace_doubles_only_when_the_dealer_hits_soft_17() {
+        assert_eq!(advise_both(&["♠6", "♥5"], "♣A"), (Play::DoubleDown, Play::Hit));
+    }
+
+    #[test]
+    fn eights_against_an_ace_surrender_only_when_the_dealer_hits_soft_17() {
+        assert_eq!(advise_both(&["♠8", "♥8"], "♣A"), (Play::Surrender, Play::Split));
+    }
+
+    #[test]
+    fn doubling_falls_back_once_the_hand_has_three_cards() {
+        let hand = hand(&["♠A", "♥2", "♦5"]);
+        let options = Options::for_hand(&hand);
+        assert!(!options.double && !options.split && !options.surrender);
+        assert_eq!(advise(&Dealer::s17(), &hand, "♣4".parse().unwrap(), options), Play::Stand);
+        assert_eq!(advise(&Dealer::s17(), &hand, "♣6".parse().unwrap(), options), Play::Stand);
+        assert_eq!(advise(&Dealer::s17(), &hand, "♣9".parse().unwrap(), options), Play::Hit);
+    }
+
+    #[test]
+    fn plays_read_back_the_way_they_are_sent() {
+        for play in [Play::Hit, Play::Stand, Play::DoubleDown, Play::Split, Play::Surrender] {
+            assert_eq!(play.to_string().parse(), Ok(play));
+        }
+    }
+}
@@ -11,7 +11,7 @@ let app = new PIXI.Application({ width: 800, height: 600 });
 
 document.getElementById('game').appendChild(app.view);
 
-PIXI.Loader.shared.add("static/cards.png").load(setup);
+PIXI.Loader.shared.add("/static/cards.png").load(setup);
 
 let texes = {};
 let strings = {};
@@ -39,53 +39,104 @@ const onReloadStrings = function () {
 
 function texture(name) {
     if (!texes[name]) {
-        texes[name] = PIXI.Loader.shared.resources[`static/${name}.png`].texture;
+        texes[name] = PIXI.Loader.shared.resources[`/static/${name}.png`].texture;
     }
 
     return texes[name];
 }
 
 let CARD;
+// The only game this client knows how to draw
+const GAME = 'BLACKJACK';
 let socket;
 let statusText;
+let timerText;
+let timerInterval = null;
 let dealerHandText;
 let playerHandText;
 let balanceText;
 let balance = 1000;
 
-function setup() {
-    app.stage.interactive = true;
-    app.stage.sortableChildren = true;
-
-    let protocol = "ws:";
-    if (window.location.protocol === "https:") {
-        protocol = "wss:";
-    }
-    socket = new WebSocket(`${protocol}//${document.location.hostname}:${document.location.port}/ws`);
-    socket.onmessage = onMessage;
-    socket.onclose = onClose;
-    socket.onopen = function () {
-        const get_code = new URLSearchParams(document.location.search).get('code');
+// Sends an action to the game, prefixed with the game's namespace
+function sendGame(action) {
+    socket.send(`${GAME} ${action}`);
+}
 
-        if (get_code == null) {
-            socket.send(`HOST BLACKJACK`)
+// Talks to the account routes, the server answers with the account or the reason it said no
+function accountRequest(path, body, onDone) {
+    const httpReq = new XMLHttpRequest();
+    httpReq.open("POST", `/account${path}`, true);
+    httpReq.setRequestHeader('Content-Type', 'application/json');
+    httpReq.onreadystatechange = function () {
+        if (httpReq.readyState != 4) return;
+        if (httpReq.status == 200) {
+            onDone(httpReq.responseText ? JSON.parse(httpReq.responseText) : null);
         } else {
-            code = get_code;
-            socket.send(`JOIN ${code}`);
+            msgBox(httpReq.responseText || httpReq.statusText, undefined, 'danger');
         }
     }
+    httpReq.send(body ? JSON.stringify(body) : null);
+}
+
+function setup() {
+    app.stage.interactive = true;
+    app.stage.sortableChildren = true;
 
     document.getElementById('formChat').onsubmit = function (event) {
         target = event.target;
         const msg = event.target.firstElementChild.value;
         event.target.firstElementChild.value = '';
 
-        socket.send(`CHAT ${msg}`);
+        const args = msg.trim().split(' ');
+        const target = playerId(args[1]);
+        if (msg.startsWith('/name ')) {
+            socket.send(`NAME ${msg.substr(6).trim()}`);
+        } else if (args[0] == '/kick' && target != null) {
+            socket.send(`KICK ${target} ${args.slice(2).join(' ')}`);
+        } else if ((args[0] == '/mute' || args[0] == '/unmute') && target != null) {
+            socket.send(`${args[0].substr(1).toUpperCase()} ${target}`);
+        } else if (args[0] == '/host' && target != null) {
+            socket.send(`HOST_IS ${target}`);
+        } else if (args[0] == '/hint') {
+            sendGame('HINT');
+        } else if (args[0] == '/side' && args.length == 3) {
+            sendGame(`SIDEBET ${args[1].toUpperCase()} ${Number(args[2]) || 0}`);
+        } else if (args[0] == '/count' && args.length == 2) {
+            sendGame(`COUNT ${Math.round(Number(args[1])) || 0}`);
+        } else if (args[0] == '/bot') {
+            socket.send(`BOT ${(args[1] || 'normal').toUpperCase()}`);
+        } else if (args[0] == '/public' || args[0] == '/private') {
+            socket.send(args[0].substr(1).toUpperCase());
+        } else if (args[0] == '/lock' || args[0] == '/unlock') {
+            socket.send(args[0].substr(1).toUpperCase());
+        } else if (args[0] == '/turntime') {
+            socket.send(`TURNTIME ${Number(args[1]) || 0}`);
+        } else if (args[0] == '/set' && args.length == 3) {
+            socket.send(`SET ${args[1]} ${args[2]}`);
+        } else if ((args[0] == '/register' || args[0] == '/login') && args.length == 3) {
+            accountRequest(args[0], { username: args[1], password: args[2] }, account => {
+                const done = args[0] == '/register' ? strings.account_registered : strings.account_logged_in;
+                msgBox(`${done} ${account.username}.`, undefined, 'info');
+            });
+        } else if (args[0] == '/logout') {
+            accountRequest('/logout', null, () => msgBox(strings.account_logged_out, undefined, 'info'));
+        } else if (args[0] == '/nick' && args.length == 2) {
+            accountRequest('/nickname', { nickname: args[1] }, account => {
+                msgBox(`${strings.account_nickname} ${account.nickname}.`, undefined, 'info');
+            });
+        } else if (args[0] == '/password') {
+            socket.send(`PASSWORD ${args[1] || ''}`.trim());
+        } else if (msg.startsWith('/spectators ')) {
+            const args = msg.substr(12).trim().split(' ');
+            socket.send(`SPECTATORS ${Number(args[0])}${args[1] == 'chat' ? ' CHAT' : ''}`);
+        } else {
+            socket.send(`CHAT ${msg}`);
+        }
 
         event.preventDefault();
     }
 
-    const cards_tex = PIXI.Loader.shared.resources["static/cards.png"].texture.baseTexture
+    const cards_tex = PIXI.Loader.shared.resources["/static/cards.png"].texture.baseTexture
     let suits = [];
     for (let y = 0; y < cards_tex.height; y += 96) {
         let cards = [];
@@ -135,8 +186,125 @@ function setup() {
     statusText = new PIXI.Text('[H]it [S]tand', {fontFamily:'Arial',fontSize:20, fill: 0xffffff, align: 'left'});
     statusText.position = {x: 4, y: 572};
     app.stage.addChild(statusText);
+
+    timerText = new PIXI.Text('', {fontFamily:'Arial',fontSize:20, fill: 0xffff00, align: 'right'});
+    timerText.position = {x: 570, y: 572};
+    app.stage.addChild(timerText);
+
+    if (typeof REPLAY !== 'undefined') {
+        // Nothing to talk to, the events come from the hand history instead
+        socket = { send() {} };
+        replay(REPLAY.code, REPLAY.round);
+    } else {
+        connect();
+    }
 }
 
+// Makes sure the browser has an account before connecting, so the wallet carries over
+function connect() {
+    const httpReq = new XMLHttpRequest();
+    httpReq.open("POST", '/account', true);
+    httpReq.onreadystatechange = function () {
+        if (httpReq.readyState != 4) return;
+        if (httpReq.status == 200) {
+            balance = JSON.parse(httpReq.responseText).wallet;
+            updateBalance(0);
+        }
+        openSocket();
+    }
+    httpReq.send(null);
+}
+
+function openSocket() {
+    let protocol = "ws:";
+    if (window.location.protocol === "https:") {
+        protocol = "wss:";
+    }
+    socket = new WebSocket(`${protocol}//${document.location.hostname}:${document.location.port}/ws`);
+    socket.onmessage = onMessage;
+    socket.onclose = onClose;
+    socket.onopen = function () {
+        const params = new URLSearchParams(document.location.search);
+        const get_code = params.get('code');
+        let name = (params.get('name') || '').replace(/\s/g, '');
+        const password = params.get('password');
+        if (password && !name) {
+            name = `${strings.player}${randomInt(1000, 10000)}`;
+        }
+        let name_arg = name ? ` ${name}` : '';
+        if (password) {
+            name_arg += ` ${password}`;
+        }
+
+        if (params.get('queue')) {
+            queued = true;
+            statusText.text = strings.queued;
+            socket.send(`QUEUE ${GAME}${name ? ` ${name}` : ''}`);
+        } else if (!get_code) {
+            socket.send(`HOST ${GAME}${name_arg}`)
+        } else if (params.get('spectate')) {
+            code = get_code;
+            spectating = true;
+            socket.send(`SPECTATE ${code}${name_arg}`);
+        } else {
+            code = get_code;
+            socket.send(`JOIN ${code}${name_arg}`);
+        }
+    }
+}
+
+// Plays a finished round back from its hand history, one entry at a time
+function replay(code, round) {
+    document.getElementById('code').innerHTML = `${code} #${round}`;
+    const httpReq = new XMLHttpRequest();
+    httpReq.open("GET", `/history/${code}/${round}`, true);
+    httpReq.onreadystatechange = function () {
+        if (httpReq.readyState != 4) {
+            return;
+        }
+        if (httpReq.status != 200) {
+            msgBox(`${strings.no_replay} ${round}`, undefined, 'error');
+            return;
+        }
+        const history = JSON.parse(httpReq.responseText);
+        const game = history.game.toUpperCase();
+        players = {};
+        for (const [pid, name] of history.players) {
+            players[pid] = name;
+        }
+        updatePlayers();
+
+        let i = 0;
+        const step = function () {
+            if (i >= history.entries.length) {
+                return;
+            }
+            const entry = history.entries[i++];
+            switch (entry.kind) {
+                case 'action':
+                    msgBox(playerName(entry.player), entry.data, 'info');
+                    break;
+                case 'event':
+                    onMessage({ data: `${game} ${entry.data}` });
+                    break;
+                case 'notice':
+                    onMessage({ data: `SYSTEM ${entry.data}` });
+                    break;
+                case 'log':
+                    if (entry.data.startsWith('MISTAKE ')) {
+                        const [, pid, played, advice] = entry.data.split(' ');
+                        msgBox(`${playerName(pid)}: ${played}, ${strings.mistake} ${advice}`, undefined, 'warning');
+                    }
+                    break;
+            }
+            setTimeout(step, entry.kind == 'log' ? 0 : 600);
+        };
+        step();
+    }
+    httpReq.send(null);
+}
+
+
 /**
  * @param {string} [sender] - Sender name.
  * @param {string} msg - Message body.
@@ -180,30 +348,113 @@ function msgBox(sender, msg, msg_class) {
     return p;
 }
 
+let players = {};
+let watchers = {};
+let host = 0;
+// Our own seat, null while spectating or until the roster says which one it is
+let me = null;
+let myName = null;
+let spectating = false;
+// Whether the server is finding us a game, see the lobby's quick match
+let queued = false;
+
+function playerName(pid) {
+    return players[pid] || watchers[pid] || `${strings.player} ${Number(pid)+1}`;
+}
+
+function playerId(name) {
+    for (const pid in players) {
+        if (players[pid] == name) return pid;
+    }
+    for (const pid in watchers) {
+        if (watchers[pid] == name) return pid;
+    }
+    return null;
+}
+
+// Whose hand is drawn, spectators and replays follow the host's
+function shownSeat() {
+    return me === null ? host : me;
+}
+
+function updatePlayers() {
+    const list = document.getElementById('listPlayers');
+    list.innerHTML = '';
+    for (const pid in players) {
+        let li = document.createElement("li");
+        li.appendChild(document.createTextNode(players[pid]));
+        if (pid == host) li.classList.add('font-weight-bold');
+        list.appendChild(li);
+    }
+    for (const pid in watchers) {
+        let li = document.createElement("li");
+        li.appendChild(document.createTextNode(`${watchers[pid]} (${strings.spectator})`));
+        li.classList.add('text-muted');
+        list.appendChild(li);
+    }
+}
+
+// This session's results so far, as [pid, hands, wins, losses, draws, net]
+let scores = [];
+
+function updateScores() {
+    const table = document.getElementById('tableScores');
+    table.innerHTML = '';
+    if (scores.length == 0) return;
+    const header = table.insertRow();
+    for (const text of ['', strings.score_hands, strings.score_record, strings.score_net]) {
+        const th = document.createElement('th');
+        th.textContent = text;
+        header.appendChild(th);
+    }
+    for (const [pid, hands, wins, losses, draws, net] of scores) {
+        const row = table.insertRow();
+        for (const text of [playerName(pid), hands, `${wins}-${losses}-${draws}`, net > 0 ? `+${net}` : net]) {
+            row.insertCell().textContent = text;
+        }
+    }
+}
+onReloadStringsCbs.push(updateScores);
+
+function parseRoster(args) {
+    let roster = {};
+    for (let i = 0; i + 1 < args.length; i += 2) {
+        roster[args[i]] = args[i+1];
+    }
+    return roster;
+}
+
 let deck = [];
 let dealerhand = []
 let playerhand = [];
+// Everyone else's hands by seat, drawn in a row to the right of the shown one
+let otherHands = {};
+// Who sits in each seat dealt in this round, as the game tells it
+let seatNames = {};
 let forceFinishNextAnimation = false;
 
 function onKeyDown(event) {
     switch (event.code) {
         case 'KeyS':
-            socket.send("STAND");
+            sendGame("STAND");
             break;
         case 'KeyH':
-            socket.send("HIT");
+            sendGame("HIT");
             break;
         case 'KeyD':
-            socket.send("DOUBLEDOWN");
+            sendGame("DOUBLEDOWN");
             break;
         case 'KeyU':
-            socket.send("SURRENDER");
+            sendGame("SURRENDER");
             break;
         case 'KeyP':
-            socket.send("SPLIT");
+            sendGame("SPLIT");
+            break;
+        case 'KeyA':
+            sendGame("HINT");
             break;
         case 'KeyN':
-            socket.send('BET 100');
+            sendGame('BET 100');
             socket.send("START");
             break;
         default:
@@ -322,30 +573,73 @@ function updateBalance(difference) {
 
 const DECK_X = 20;
 const DECK_Y = 10;
+const OTHER_HANDS_X = 280;
+const OTHER_HANDS_SPACING = 170;
+
+function seatName(pid) {
+    return seatNames[pid] || playerName(pid);
+}
+
+function otherHand(pid) {
+    if (!(pid in otherHands)) {
+        const x = OTHER_HANDS_X + Object.keys(otherHands).length * OTHER_HANDS_SPACING;
+        const text = new PIXI.Text(seatName(pid), {fontFamily:'Arial',fontSize:16, fill: 0xffffff, align: 'left'});
+        text.position = {x: x, y: 495};
+        app.stage.addChild(text);
+        // Their down card is only ever shown to them, so the hand starts with its back
+        otherHands[pid] = {x: x, text: text, cards: [drawCard(x, hole_card_y)]};
+    }
+    return otherHands[pid];
+}
 
 function onMessage(event) {
-    console.log(`got ${event.data}`);
-    if (event.data.startsWith('PING')) {
+    // Game events come in the game's namespace, everything else is about the session
+    const data = event.data.startsWith(`${GAME} `) ? event.data.substr(GAME.length + 1) : event.data;
+    console.log(`got ${data}`);
+    if (data.startsWith('PING')) {
         socket.send('PONG');
-    } else if (event.data.startsWith('HOST_OK')) {
-        socket.send('BET 100');
-        socket.send("START");
-    } else if (event.data.startsWith('LOSE')) {
+    } else if (data.startsWith('HOST_OK') || data.startsWith('JOIN_OK ')) {
+        const [ok, joined, name] = data.split(' ');
+        code = joined;
+        if (ok == 'HOST_OK') {
+            me = '0';
+        } else {
+            myName = name;
+        }
+        if (ok == 'HOST_OK' || queued) {
+            sendGame('BET 100');
+            socket.send("START");
+        }
+    } else if (data.startsWith('LOSE')) {
         statusText.text = 'You lost! :( ' + statusText.text;
-    } else if (event.data.startsWith('WIN')) {
+    } else if (data.startsWith('WIN')) {
         statusText.text = 'You won!!!  ' + statusText.text;
-    } else if (event.data.startsWith('DRAW')) {
+    } else if (data.startsWith('DRAW')) {
         statusText.text = 'You tied! You get the bet back. ' + statusText.text;
-    } else if (event.data.startsWith('TAKEMONEY ')) {
-        const args = event.data.substr(10).split(' ');
+    } else if (data.startsWith('HINT ')) {
+        msgBox(`${strings.hint} ${data.substr(5)}`, undefined, 'info');
+    } else if (data.startsWith('SIDEBET ')) {
+        msgBox(`${strings.side_bet_placed} ${data.substr(8)}`, undefined, 'info');
+    } else if (data.startsWith('SIDEWIN ')) {
+        msgBox(`${strings.side_bet_won} ${data.substr(8)}`, undefined, 'info');
+    } else if (data.startsWith('SIDELOSE ')) {
+        msgBox(`${strings.side_bet_lost} ${data.substr(9)}`, undefined, 'info');
+    } else if (data.startsWith('QUIZ ')) {
+        msgBox(data.substr(5) == 'TRUE' ? strings.quiz_true : strings.quiz_running, undefined, 'info');
+    } else if (data.startsWith('COUNTED ')) {
+        const [right, answer, correct, asked] = data.substr(8).split(' ');
+        const verdict = right == 'RIGHT' ? strings.count_right : strings.count_wrong;
+        msgBox(`${verdict} ${answer}. ${strings.count_score} ${correct}/${asked}`, undefined, right == 'RIGHT' ? 'info' : 'danger');
+    } else if (data.startsWith('TAKEMONEY ')) {
+        const args = data.substr(10).split(' ');
         const money = Number(args[0]);
         updateBalance(-money);
-    } else if (event.data.startsWith('SENDMONEY ')) {
-        const args = event.data.substr(10).split(' ');
+    } else if (data.startsWith('SENDMONEY ')) {
+        const args = data.substr(10).split(' ');
         const money = Number(args[0]);
         updateBalance(money);
-    } else if (event.data.startsWith('DECKSIZE ')) {
-        const args = event.data.substr(9).split(' ');
+    } else if (data.startsWith('DECKSIZE ')) {
+        const args = data.substr(9).split(' ');
 
         const decksize = Number(args[0]) / 5;
         while (deck.length < decksize) {
@@ -357,25 +651,34 @@ function onMessage(event) {
             const card = deck.pop();
             app.stage.removeChild(card);
         }
-    } else if (event.data.startsWith('START')) {
+    } else if (data.startsWith('START')) {
         dealerhand.forEach(spr => app.stage.removeChild(spr));
         dealerhand = [];
         playerhand.forEach(spr => app.stage.removeChild(spr));
         playerhand = [];
-    } else if (event.data.startsWith('VALUEUPDATE ')) {
-        const args = event.data.substr(12).split(' ');
+        for (const pid in otherHands) {
+            otherHands[pid].cards.forEach(spr => app.stage.removeChild(spr));
+            app.stage.removeChild(otherHands[pid].text);
+        }
+        otherHands = {};
+        seatNames = {};
+    } else if (data.startsWith('SEAT ')) {
+        const [pid, name] = data.substr(5).split(' ');
+        seatNames[pid] = name;
+    } else if (data.startsWith('VALUEUPDATE ')) {
+        const args = data.substr(12).split(' ');
         const soft = args[args.length-1] == 'soft';
         const value = Number(args[args.length-(soft?2:1)]);
-        let text;
-        if (args.length > (soft?2:1)) {
-            text = playerHandText;
+        const shown = soft ? `${value} or ${value - 10}` : `${value}`;
+        if (args.length == (soft?2:1)) {
+            dealerHandText.text = `Value: ${shown}`;
+        } else if (args[0] == shownSeat()) {
+            playerHandText.text = `Value: ${shown}`;
         } else {
-            text = dealerHandText;
+            otherHand(args[0]).text.text = `${seatName(args[0])}: ${shown}`;
         }
-        text.text = `Value: ${value}`;
-        if (soft) text.text += ` or ${value - 10}`;
-    } else if (event.data.startsWith('STATUS ')) {
-        const args = event.data.substr(7).split(' ');
+    } else if (data.startsWith('STATUS ')) {
+        const args = data.substr(7).split(' ');
         statusText.text = ' ';
         for (const arg of args) {
             switch (arg) {
@@ -403,8 +706,8 @@ function onMessage(event) {
             }
         }
         
-    } else if (event.data.startsWith('REVEALDOWNS ')) {
-        const args = event.data.substr(12).split(' ');
+    } else if (data.startsWith('REVEALDOWNS ')) {
+        const args = data.substr(12).split(' ');
         const c = parseCard(args[0]);
         const card = dealerhand[0];
 
@@ -414,8 +717,8 @@ function onMessage(event) {
             card.zIndex -= 2;
             queueAnimation(new CardAnimation(card, 15, 200, 0.35));
         }));
-    } else if (event.data.startsWith('DOWNCARD ')) {
-        const args = event.data.substr(9).split(' ');
+    } else if (data.startsWith('DOWNCARD ')) {
+        const args = data.substr(9).split(' ');
         const c = parseCard(args[0]);
 
         const card = drawCard(hole_card_x, hole_card_y, c);
@@ -425,8 +728,8 @@ function onMessage(event) {
             const card = drawCard(15, 200);
             dealerhand.push(card);
         }
-    } else if (event.data.startsWith('DEALERDRAW ')) {
-        const args = event.data.substr(11).split(' ');
+    } else if (data.startsWith('DEALERDRAW ')) {
+        const args = data.substr(11).split(' ');
 
         const y = 200;
 
@@ -436,26 +739,142 @@ function onMessage(event) {
         }
         const c = parseCard(args[0]);
         queueAnimation(dummyAnimation(() => dealerhand.push(drawCard(15 + dealerhand.length * increment, y, c))));
-    } else if (event.data.startsWith('PLAYERDRAW ')) {
-        const args = event.data.substr(11).split(' ');
+    } else if (data.startsWith('PLAYERDRAW ')) {
+        const args = data.substr(11).split(' ');
 
         const c = parseCard(args[1]);
 
+        // Everyone's cards are dealt in the open
+        if (args[0] != shownSeat()) {
+            const hand = otherHand(args[0]);
+            hand.cards.push(drawCard(hand.x + increment * hand.cards.length, hole_card_y, c));
+            return;
+        }
+
+        if (spectating && playerhand.length == 0) {
+            playerhand.push(drawCard(hole_card_x, hole_card_y));
+        }
         const card = drawCard(hole_card_x + increment * playerhand.length, hole_card_y, c);
         playerhand.push(card);
-    } else if (event.data.startsWith('CHAT_MSG ')) {
-        const body = event.data.substr('CHAT_MSG '.length);
+    } else if (data.startsWith('PLAYERS')) {
+        players = parseRoster(data.substr(8).split(' '));
+        if (myName !== null) me = playerId(myName);
+        updatePlayers();
+        updateScores();
+    } else if (data.startsWith('WATCHERS')) {
+        watchers = parseRoster(data.substr(9).split(' '));
+        updatePlayers();
+    } else if (data.startsWith('TIMER')) {
+        const args = data.substr(6).split(' ');
+        clearInterval(timerInterval);
+        timerText.text = '';
+        if (args.length == 2) {
+            const who = playerName(args[0]);
+            let seconds = Number(args[1]);
+            const tick = () => {
+                timerText.text = `${who}: ${seconds}s`;
+                if (seconds-- <= 0) clearInterval(timerInterval);
+            };
+            tick();
+            timerInterval = setInterval(tick, 1000);
+        }
+    } else if (data.startsWith('TURNTIME ')) {
+        msgBox(`${strings.turn_time} ${data.substr(9)}s`, undefined, 'info');
+    } else if (data.startsWith('SETTING ')) {
+        const [key, kind, value] = data.substr(8).split(' ');
+        msgBox(`${strings.setting} ${key} = ${value} (${kind.split(':')[1] || 'ON/OFF'})`, undefined, 'info');
+    } else if (data.startsWith('SCORES')) {
+        const args = data.substr(7).split(' ').filter(a => a != '');
+        scores = [];
+        for (let i = 0; i + 5 < args.length; i += 6) {
+            scores.push(args.slice(i, i + 6));
+        }
+        updateScores();
+    } else if (data.startsWith('ROUND_OVER ')) {
+        const round = data.substr(11);
+        const p = msgBox(`${strings.round_over} ${round}.`, undefined, 'info');
+        const a = document.createElement('a');
+        a.href = `/replay/${code}/${round}`;
+        a.target = '_blank';
+        a.textContent = ` ${strings.replay}`;
+        p.appendChild(a);
+    } else if (data == 'FINISHED') {
+        msgBox(strings.finished, undefined, 'info');
+    } else if (data.startsWith('HOST_IS ')) {
+        host = data.substr(8);
+        updatePlayers();
+    } else if (data.startsWith('MUTE ')) {
+        msgBox(`${playerName(data.substr(5))} ${strings.was_muted}`, undefined, 'info');
+    } else if (data.startsWith('UNMUTE ')) {
+        msgBox(`${playerName(data.substr(7))} ${strings.was_unmuted}`, undefined, 'info');
+    } else if (data == 'LOCK') {
+        msgBox(strings.locked, undefined, 'info');
+    } else if (data == 'UNLOCK') {
+        msgBox(strings.unlocked, undefined, 'info');
+    } else if (data == 'PUBLIC') {
+        msgBox(strings.now_public, undefined, 'info');
+    } else if (data == 'PRIVATE') {
+        msgBox(strings.now_private, undefined, 'info');
+    } else if (data.startsWith('SPECTATE_OK ')) {
+        statusText.text = strings.spectating;
+    } else if (data.startsWith('SPECTATORS ')) {
+        const args = data.substr(11).split(' ');
+        msgBox(`${strings.spectators_allowed} ${args[0]}`, undefined, 'info');
+    } else if (data.startsWith('SYSTEM ')) {
+        const body = data.substr(7);
+        const kind = body.split(' ')[0];
+        const arg = body.substr(kind.length + 1);
+        switch (kind) {
+            case 'JOINED':
+                msgBox(`${arg} ${strings.joined}`, undefined, 'info');
+                break;
+            case 'LEFT':
+                msgBox(`${arg} ${strings.left}`, undefined, 'info');
+                break;
+            case 'KICKED':
+                msgBox(`${arg} ${strings.kicked}`, undefined, 'info');
+                break;
+            case 'WIN':
+                msgBox(`${playerName(arg)} ${strings.won}`, undefined, 'info');
+                break;
+            case 'LOSE':
+                msgBox(`${playerName(arg)} ${strings.lost}`, undefined, 'info');
+                break;
+            case 'DRAW':
+                msgBox(`${playerName(arg)} ${strings.tied}`, undefined, 'info');
+                break;
+            default:
+                msgBox(arg, undefined, 'info');
+                break;
+        }
+    } else if (data.startsWith('ERROR ')) {
+        msgBox(data.substr(6), undefined, 'error');
+    } else if (data.startsWith('CHAT_MSG ')) {
+        const body = data.substr('CHAT_MSG '.length);
         const sender = body.split(' ')[0];
-        const sender_name = `${strings.player} ${Number(sender)+1}`;
+        const sender_name = playerName(sender);
         const msg = body.substr(sender.length + 1);
 
         msgBox(sender_name, msg);
     } else {
-        console.log(`unknown packet ${event.data}`);
+        console.log(`unknown packet ${data}`);
     }
 }
 function onClose(event) {
-
+    if (event.reason == 'Game full' && confirm(strings.spectate_instead)) {
+        const params = new URLSearchParams(document.location.search);
+        params.set('spectate', '1');
+        document.location.search = params.toString();
+    } else if (event.reason == 'Wrong password') {
+        const password = prompt(strings.enter_password);
+        if (password) {
+            const params = new URLSearchParams(document.location.search);
+            params.set('password', password.replace(/\s/g, ''));
+            document.location.search = params.toString();
+        }
+    } else if (event.reason) {
+        msgBox(`${strings.close_error} ${event.reason}`, undefined, 'error');
+    }
 }
 
 function parseCard(s) {
@@ -0,0 +1,417 @@
+//! Scripted conversations over `/ws` with a server started for each test
+//!
+//! The server is run with its shoe seeded, so every card dealt is known in advance
+//! and what each client is sent can be asserted line by line.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use websocket::sync::Client as WsClient;
+use websocket::{ClientBuilder, OwnedMessage};
+
+/// What the shoe is shuffled with, the deals below are what it gives
+const SEED: &str = "42";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The server binary, killed when the test is done with it
+struct Server {
+    process: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start() -> Self {
+        // Another test could take the port in between, but they're all started the same way
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let process = Command::new(env!("CARGO_BIN_EXE_fellestrekk"))
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .env("ROCKET_PORT", port.to_string())
+            .env("ROCKET_DECK_SEED", SEED)
+            .env_remove("ROCKET_STORAGE_DIR")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("starting the server");
+        let server = Server { process, port };
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(started.elapsed() < TIMEOUT, "the server didn't start listening");
+            thread::sleep(Duration::from_millis(50));
+        }
+        server
+    }
+    fn connect(&self) -> Client {
+        let client = ClientBuilder::new(&format!("ws://127.0.0.1:{}/ws", self.port))
+            .unwrap()
+            .connect_insecure()
+            .expect("connecting to the server");
+        client.stream_ref().set_read_timeout(Some(TIMEOUT)).unwrap();
+        Client(client)
+    }
+    /// Hosts a game, giving back the host and the session's code
+    fn host(&self, game: &str, name: &str) -> (Client, String) {
+        let mut host = self.connect();
+        host.send(&format!("HOST {game} {name}"));
+        let ok = host.recv();
+        let code = ok.strip_prefix("HOST_OK ").unwrap_or_else(|| panic!("expected HOST_OK, got {ok:?}"));
+        (host, code.to_owned())
+    }
+    /// What `/metrics` says right now
+    fn metrics(&self) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        write!(stream, "GET /metrics HTTP/1.0\r\nHost: 127.0.0.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").expect("an HTTP response");
+        assert!(head.starts_with("HTTP/1.0 200") || head.starts_with("HTTP/1.1 200"), "{head}");
+        body.to_owned()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+struct Client(WsClient<TcpStream>);
+
+impl Client {
+    fn send(&mut self, text: &str) {
+        self.0.send_message(&OwnedMessage::Text(text.to_owned())).unwrap();
+    }
+    fn message(&mut self) -> OwnedMessage {
+        loop {
+            match self.0.recv_message().expect("a message from the server") {
+                OwnedMessage::Ping(data) => self.0.send_message(&OwnedMessage::Pong(data)).unwrap(),
+                OwnedMessage::Pong(_) => (),
+                msg => return msg,
+            }
+        }
+    }
+    fn recv(&mut self) -> String {
+        match self.message() {
+            OwnedMessage::Text(text) => text,
+            msg => panic!("expected a text message, got {msg:?}"),
+        }
+    }
+    /// Asserts the next messages are exactly these, in this order
+    fn expect(&mut self, lines: &[&str]) {
+        for &line in lines {
+            assert_eq!(self.recv(), line);
+        }
+    }
+    fn expect_close(&mut self, code: u16, reason: &str) {
+        match self.message() {
+            OwnedMessage::Close(Some(data)) => assert_eq!((data.status_code, &*data.reason), (code, reason)),
+            msg => panic!("expected the connection to close, got {msg:?}"),
+        }
+    }
+}
+
+const BLACKJACK_SETTINGS: [&str; 4] = [
+    "SETTING dealer CHOICE:H17,S17 H17",
+    "SETTING counting CHOICE:OFF,HILO,KO,OMEGA2 OFF",
+    "SETTING pairs CHOICE:OFF,25/12/6,30/10/5 25/12/6",
+    "SETTING 21+3 CHOICE:OFF,100/40/30/10/5,9 100/40/30/10/5",
+];
+
+#[test]
+fn host_bet_hit_and_stand() {
+    let server = Server::start();
+    let (mut alice, _) = server.host("BLACKJACK", "Alice");
+    alice.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0"]);
+    alice.expect(&BLACKJACK_SETTINGS);
+    alice.expect(&["PLAYERS 0 Alice"]);
+
+    alice.send("BLACKJACK BET 100");
+    alice.expect(&["BLACKJACK TAKEMONEY 100", "BLACKJACK DECKSIZE 0"]);
+
+    alice.send("START");
+    alice.expect(&[
+        "BLACKJACK START",
+        "BLACKJACK SEAT 0 Alice",
+        "BLACKJACK DECKSIZE 52",
+        "BLACKJACK VALUEUPDATE 4",
+        "BLACKJACK DOWNCARD ♠2",
+        "BLACKJACK PLAYERDRAW 0 ♦K",
+        "BLACKJACK DEALERDRAW ♦4",
+        "BLACKJACK VALUEUPDATE 0 12",
+        "BLACKJACK STATUS H S D U",
+        "BLACKJACK DECKSIZE 48",
+        "TIMER 0 30",
+    ]);
+
+    alice.send("BLACKJACK HIT");
+    alice.expect(&[
+        "BLACKJACK PLAYERDRAW 0 ♠5",
+        "BLACKJACK VALUEUPDATE 0 17",
+        "BLACKJACK STATUS H S",
+        "BLACKJACK DECKSIZE 47",
+    ]);
+
+    alice.send("BLACKJACK STAND");
+    alice.expect(&[
+        "BLACKJACK STATUS N",
+        "BLACKJACK REVEALDOWNS ♣K ♠2",
+        "BLACKJACK DEALERDRAW ♦8",
+        "BLACKJACK VALUEUPDATE 22",
+        "SYSTEM WIN 0",
+        "BLACKJACK WIN",
+        "BLACKJACK SENDMONEY 200",
+        "BLACKJACK DECKSIZE 46",
+        "SCORES 0 1 1 0 0 100",
+        "ROUND_OVER 1",
+        "TIMER",
+    ]);
+}
+
+#[test]
+fn bets_past_the_limit_are_refused() {
+    let server = Server::start();
+    let (mut alice, _) = server.host("BLACKJACK", "Alice");
+    alice.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0"]);
+    alice.expect(&BLACKJACK_SETTINGS);
+    alice.expect(&["PLAYERS 0 Alice"]);
+
+    alice.send("BLACKJACK SIDEBET 21+3 10001");
+    alice.expect(&["ERROR Side bets can be at most 10000"]);
+    alice.send("BLACKJACK BET 4294967295");
+    alice.expect(&["ERROR Bets can be at most 100000"]);
+
+    alice.send("BLACKJACK SIDEBET 21+3 1");
+    alice.expect(&["BLACKJACK TAKEMONEY 1", "BLACKJACK SIDEBET 21+3 1"]);
+    alice.send("BLACKJACK BET 100000");
+    alice.expect(&["BLACKJACK TAKEMONEY 100000"]);
+}
+
+#[test]
+fn join_and_chat() {
+    let server = Server::start();
+    let (mut carol, code) = server.host("CHATROOM", "Carol");
+    carol.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0", "PLAYERS 0 Carol"]);
+
+    let mut bob = server.connect();
+    bob.send(&format!("JOIN {code} Bob"));
+    bob.expect(&[
+        &format!("JOIN_OK {code} Bob"),
+        "HOST_IS 0",
+        "SCORES 0 0 0 0 0 0",
+        "PLAYERS 0 Carol 1 Bob",
+        "SYSTEM JOINED Bob",
+    ]);
+    carol.expect(&["PLAYERS 0 Carol 1 Bob", "SYSTEM JOINED Bob"]);
+
+    bob.send("CHAT hello there");
+    bob.expect(&["CHAT_MSG 1 hello there"]);
+    carol.expect(&["CHAT_MSG 1 hello there"]);
+
+    carol.send("CHAT hi Bob");
+    carol.expect(&["CHAT_MSG 0 hi Bob"]);
+    bob.expect(&["CHAT_MSG 0 hi Bob"]);
+}
+
+#[test]
+fn kick_reasons_fit_in_a_close_frame() {
+    let server = Server::start();
+    let (mut carol, code) = server.host("CHATROOM", "Carol");
+    carol.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0", "PLAYERS 0 Carol"]);
+    let mut bob = server.connect();
+    bob.send(&format!("JOIN {code} Bob"));
+    bob.expect(&[&format!("JOIN_OK {code} Bob"), "HOST_IS 0", "SCORES 0 0 0 0 0 0", "PLAYERS 0 Carol 1 Bob", "SYSTEM JOINED Bob"]);
+    carol.expect(&["PLAYERS 0 Carol 1 Bob", "SYSTEM JOINED Bob"]);
+
+    carol.send(&format!("KICK 1 {}", "ø".repeat(62)));
+    carol.expect(&["ERROR Kick reason too long"]);
+
+    carol.send("KICK 1 spamming");
+    bob.expect_close(1008, "spamming");
+}
+
+#[test]
+fn joining_a_missing_game() {
+    let server = Server::start();
+    let (_carol, code) = server.host("CHATROOM", "Carol");
+    // The only session on this server is Carol's
+    let missing = u16::from_str_radix(&code, 16).unwrap() ^ 1;
+    let mut bob = server.connect();
+    bob.send(&format!("JOIN {missing:X} Bob"));
+    bob.expect_close(1008, "No such game");
+}
+
+#[test]
+fn joining_takes_the_password() {
+    let server = Server::start();
+    let (mut carol, code) = server.host("CHATROOM", "Carol");
+    carol.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0", "PLAYERS 0 Carol"]);
+    carol.send("PASSWORD hunter2");
+    // Answered once the password is in place
+    carol.send("SCORES");
+    carol.expect(&["SCORES 0 0 0 0 0 0"]);
+
+    let mut bob = server.connect();
+    bob.send(&format!("JOIN {code} Bob hunter3"));
+    bob.expect_close(1008, "Wrong password");
+
+    let mut bob = server.connect();
+    bob.send(&format!("JOIN {code} Bob hunter2"));
+    bob.expect(&[&format!("JOIN_OK {code} Bob")]);
+}
+
+#[test]
+fn starting_with_anything_but_a_seat() {
+    let server = Server::start();
+    let mut bob = server.connect();
+    bob.send("CHAT hello");
+    bob.expect_close(1008, "Expected HOST, JOIN, SPECTATE or QUEUE");
+}
+
+#[test]
+fn queued_players_share_a_table() {
+    let server = Server::start();
+    let mut alice = server.connect();
+    alice.send("QUEUE BLACKJACK Alice");
+    let ok = alice.recv();
+    let code = ok.strip_prefix("JOIN_OK ").and_then(|ok| ok.strip_suffix(" Alice"))
+        .unwrap_or_else(|| panic!("expected JOIN_OK, got {ok:?}"));
+    alice.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0"]);
+    alice.expect(&BLACKJACK_SETTINGS);
+    alice.expect(&["PLAYERS 0 Alice"]);
+
+    // Blackjack starts with one player, so Alice opened a table and Bob is seated at it
+    let mut bob = server.connect();
+    bob.send("QUEUE BLACKJACK Bob");
+    bob.expect(&[&format!("JOIN_OK {code} Bob"), "HOST_IS 0"]);
+    alice.expect(&["PLAYERS 0 Alice 1 Bob", "SYSTEM JOINED Bob"]);
+}
+
+#[test]
+fn spectators_see_the_deal_but_not_the_hole_cards() {
+    let server = Server::start();
+    let (mut alice, code) = server.host("BLACKJACK", "Alice");
+    alice.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0"]);
+    alice.expect(&BLACKJACK_SETTINGS);
+    alice.expect(&["PLAYERS 0 Alice"]);
+
+    let mut sam = server.connect();
+    sam.send(&format!("SPECTATE {code} Sam"));
+    sam.expect(&[&format!("SPECTATE_OK {code} Sam"), "HOST_IS 0", "SCORES 0 0 0 0 0 0"]);
+    sam.expect(&BLACKJACK_SETTINGS);
+    sam.expect(&["WATCHERS 1 Sam", "SYSTEM JOINED Sam"]);
+    alice.expect(&["WATCHERS 1 Sam", "SYSTEM JOINED Sam"]);
+
+    alice.send("BLACKJACK BET 100");
+    alice.send("START");
+    sam.expect(&[
+        "BLACKJACK DECKSIZE 0",
+        "BLACKJACK START",
+        "BLACKJACK SEAT 0 Alice",
+        "BLACKJACK DECKSIZE 52",
+        "BLACKJACK VALUEUPDATE 4",
+        "BLACKJACK PLAYERDRAW 0 ♦K",
+        "BLACKJACK DEALERDRAW ♦4",
+        "BLACKJACK DECKSIZE 48",
+        "TIMER 0 30",
+    ]);
+
+    alice.send("BLACKJACK HIT");
+    alice.send("BLACKJACK HIT");
+    sam.expect(&[
+        "BLACKJACK PLAYERDRAW 0 ♠5",
+        "BLACKJACK DECKSIZE 47",
+        "BLACKJACK PLAYERDRAW 0 ♦8",
+        "BLACKJACK REVEALDOWNS ♣K ♠2",
+        "BLACKJACK DEALERDRAW ♥6",
+        "BLACKJACK VALUEUPDATE 20",
+        "SYSTEM LOSE 0",
+        "BLACKJACK DECKSIZE 45",
+        "SCORES 0 1 0 1 0 -100",
+        "ROUND_OVER 1",
+        "TIMER",
+    ]);
+}
+
+#[test]
+fn a_bot_plays_a_round_at_the_table() {
+    let server = Server::start();
+    let (mut alice, _) = server.host("BLACKJACK", "Alice");
+    alice.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0"]);
+    alice.expect(&BLACKJACK_SETTINGS);
+    alice.expect(&["PLAYERS 0 Alice"]);
+
+    alice.send("BOT HARD");
+    alice.expect(&["PLAYERS 0 Alice 1 Bot1", "SYSTEM JOINED Bot1"]);
+    // Its bet going down is what makes the table tell the deck's size
+    alice.expect(&["BLACKJACK DECKSIZE 0"]);
+
+    alice.send("BLACKJACK BET 100");
+    alice.expect(&["BLACKJACK TAKEMONEY 100"]);
+    alice.send("START");
+    alice.expect(&[
+        "BLACKJACK START",
+        "BLACKJACK SEAT 0 Alice",
+        "BLACKJACK SEAT 1 Bot1",
+        "BLACKJACK DECKSIZE 52",
+        "BLACKJACK VALUEUPDATE 8",
+        "BLACKJACK DOWNCARD ♠2",
+        "BLACKJACK PLAYERDRAW 0 ♦4",
+        "BLACKJACK PLAYERDRAW 1 ♠5",
+        "BLACKJACK DEALERDRAW ♦8",
+        "BLACKJACK VALUEUPDATE 0 6",
+        "BLACKJACK STATUS H S D U",
+        "BLACKJACK DECKSIZE 46",
+        "TIMER 0 30",
+    ]);
+
+    // The bot has ♣K and ♠5 against the dealer's ♦8, basic strategy hits and stands on 21
+    alice.send("BLACKJACK STAND");
+    alice.expect(&[
+        "BLACKJACK STATUS N",
+        "TIMER 1 30",
+        "BLACKJACK PLAYERDRAW 1 ♥6",
+        "BLACKJACK DECKSIZE 45",
+        "BLACKJACK REVEALDOWNS ♦K ♠2 ♣K",
+        "BLACKJACK VALUEUPDATE 18",
+        "SYSTEM LOSE 0",
+        "BLACKJACK LOSE",
+        "SYSTEM WIN 1",
+        "SCORES 0 1 0 1 0 -100 1 1 1 0 0 100",
+        "ROUND_OVER 1",
+        "TIMER",
+    ]);
+}
+
+#[test]
+fn metrics_count_the_round() {
+    let server = Server::start();
+    let (mut alice, _) = server.host("BLACKJACK", "Alice");
+    alice.expect(&["HOST_IS 0", "SCORES 0 0 0 0 0 0"]);
+    alice.expect(&BLACKJACK_SETTINGS);
+    alice.expect(&["PLAYERS 0 Alice"]);
+
+    alice.send("BLACKJACK BET 100");
+    alice.send("START");
+    // Standing on 12 against the dealer's ♦4 and ♣K, who draws ♠5 to 19
+    alice.send("BLACKJACK STAND");
+    while alice.recv() != "ROUND_OVER 1" {}
+
+    let metrics = server.metrics();
+    for line in [
+        "fellestrekk_sessions{game=\"BLACKJACK\"} 1",
+        "fellestrekk_rounds_total{game=\"BLACKJACK\"} 1",
+        "fellestrekk_chips_wagered_total{game=\"BLACKJACK\"} 100",
+        "fellestrekk_chips_paid_out_total{game=\"BLACKJACK\"} 0",
+        "fellestrekk_messages_received_total{command=\"START\"} 1",
+        "fellestrekk_messages_received_total{command=\"BLACKJACK_BET\"} 1",
+        "fellestrekk_messages_received_total{command=\"BLACKJACK_STAND\"} 1",
+        "fellestrekk_messages_sent_total{command=\"BLACKJACK_STATUS\"} 2",
+        "fellestrekk_messages_sent_total{command=\"ROUND_OVER\"} 1",
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "{line} missing from\n{metrics}");
+    }
+}